@@ -0,0 +1,49 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use crossbeam::channel;
+use foxterm::{loaded_font::LoadedFont, terminal::config::Config, Performer};
+use std::sync::{Arc, RwLock};
+use winit::dpi::PhysicalSize;
+
+// Something like `ls -la --color` in a big directory, colored names scrolling a full screen by.
+fn listing() -> Vec<u8> {
+    (0..2000)
+        .flat_map(|i| {
+            format!(
+                "-rw-r--r--  1 user user {:>8} Oct 14 12:00 \x1b[01;34mfile{}.rs\x1b[0m\r\n",
+                i * 37,
+                i
+            )
+            .into_bytes()
+        })
+        .collect()
+}
+
+fn performer(font: Arc<LoadedFont>) -> Performer {
+    let (writer, _) = channel::unbounded();
+
+    Performer::default(
+        font,
+        Arc::new(RwLock::new(Vec::new())),
+        writer,
+        PhysicalSize::new(800, 600),
+    )
+}
+
+fn process(c: &mut Criterion) {
+    let font = Arc::new(LoadedFont::from_file(&Config::default(), 1.0).unwrap());
+    let bytes = listing();
+    let mut group = c.benchmark_group("process");
+
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function("ls", |b| {
+        b.iter_batched(
+            || performer(font.clone()),
+            |mut performer| performer.process(&bytes),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, process);
+criterion_main!(benches);
@@ -0,0 +1,81 @@
+use std::{env, path::PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub working_directory: Option<PathBuf>,
+    pub list_gpus: bool,
+    pub dump_config: bool,
+    pub vulkan_validation: bool,
+    pub replay: Option<PathBuf>,
+    pub replay_rate: usize,
+    pub command: Vec<String>,
+}
+
+impl Args {
+    pub fn parse() -> anyhow::Result<Self> {
+        Self::parse_from(env::args().skip(1))
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-d" | "--working-directory" => {
+                    let path = args.next().ok_or(ArgsError::MissingValue(arg))?;
+
+                    parsed.working_directory = Some(Self::directory(path)?);
+                }
+                "--list-gpus" => parsed.list_gpus = true,
+                "--dump-config" => parsed.dump_config = true,
+                "--vulkan-validation" => parsed.vulkan_validation = true,
+                // Feeds a captured session through the parser, in bytes per second or all at once.
+                "--replay" => {
+                    let path = args.next().ok_or(ArgsError::MissingValue(arg))?;
+
+                    parsed.replay = Some(PathBuf::from(shellexpand::tilde(&path).as_ref()));
+                }
+                "--replay-rate" => {
+                    let rate = args.next().ok_or(ArgsError::MissingValue(arg.clone()))?;
+
+                    parsed.replay_rate = rate.parse().map_err(|_| ArgsError::InvalidValue(arg))?;
+                }
+                // Everything after -e is the command and its arguments.
+                "-e" | "--command" => {
+                    parsed.command = args.by_ref().collect();
+
+                    if parsed.command.is_empty() {
+                        return Err(ArgsError::MissingValue(arg).into());
+                    }
+                }
+                _ => return Err(ArgsError::UnknownArgument(arg).into()),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn directory(path: String) -> anyhow::Result<PathBuf> {
+        let path = PathBuf::from(shellexpand::tilde(&path).as_ref());
+
+        if path.is_dir() {
+            Ok(path)
+        } else {
+            Err(ArgsError::NotADirectory(path).into())
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ArgsError {
+    #[error("Error: missing value for {0}")]
+    MissingValue(String),
+    #[error("Error: invalid value for {0}")]
+    InvalidValue(String),
+    #[error("Error: unknown argument {0}")]
+    UnknownArgument(String),
+    #[error("Error: {0:?} is not a directory")]
+    NotADirectory(PathBuf),
+}
@@ -0,0 +1,44 @@
+use crate::terminal::POLL_INTERVAL;
+use nix::sys::signal::{self, SigHandler, Signal};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+use winit::{dpi::PhysicalSize, event_loop::EventLoopProxy, window::Window};
+
+pub const HEIGHT: f32 = 0.5;
+
+static TOGGLE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_toggle(_: i32) {
+    TOGGLE_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+pub fn place(window: &Window) {
+    if let Some(monitor) = window.current_monitor() {
+        let size = monitor.size();
+
+        window.set_outer_position(monitor.position());
+        window.set_inner_size(PhysicalSize::new(
+            size.width,
+            (size.height as f32 * HEIGHT) as u32,
+        ));
+    }
+}
+
+// A hidden window gets no key events, so until there is a global hotkey SIGUSR1 brings it back,
+// e.g. from a desktop shortcut running `pkill -USR1 foxterm`. The handler only sets a flag, the
+// event loop is woken from a thread.
+pub fn listen(proxy: EventLoopProxy<()>) -> anyhow::Result<()> {
+    unsafe { signal::signal(Signal::SIGUSR1, SigHandler::Handler(request_toggle))? };
+
+    thread::spawn(move || loop {
+        thread::sleep(POLL_INTERVAL);
+
+        if TOGGLE_REQUESTED.swap(false, Ordering::SeqCst) && proxy.send_event(()).is_err() {
+            break;
+        }
+    });
+
+    Ok(())
+}
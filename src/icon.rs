@@ -0,0 +1,150 @@
+use crate::loaded_font::FALLBACK_FONT;
+use fontdue::{Font, FontSettings};
+use std::{fs, path::Path};
+use thiserror::Error;
+use winit::window::Icon;
+
+pub const ICON_SIZE: usize = 64;
+pub const ICON_BACKGROUND: [u8; 4] = [32, 32, 32, u8::MAX];
+
+pub const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+pub fn load(path: &Path) -> anyhow::Result<Icon> {
+    let bytes = fs::read(shellexpand::tilde(&path.to_string_lossy()).as_ref())?;
+    let (width, height, rgba) = if bytes.starts_with(PNG_SIGNATURE) {
+        decode_png(&bytes)?
+    } else {
+        decode_pam(&bytes)?
+    };
+
+    Ok(Icon::from_rgba(rgba, width, height)?)
+}
+
+pub fn default_icon() -> anyhow::Result<Icon> {
+    let font = Font::from_bytes(FALLBACK_FONT, FontSettings::default()).map_err(IconError::Font)?;
+    let (metrics, bitmap) = font.rasterize('f', ICON_SIZE as f32 * 0.75);
+    let left = ICON_SIZE.saturating_sub(metrics.width) / 2;
+    let top = ICON_SIZE.saturating_sub(metrics.height) / 2;
+    let mut rgba = ICON_BACKGROUND.repeat(ICON_SIZE * ICON_SIZE);
+
+    for (i, coverage) in bitmap.iter().enumerate() {
+        let (x, y) = (left + i % metrics.width, top + i / metrics.width);
+
+        if x < ICON_SIZE && y < ICON_SIZE {
+            for channel in &mut rgba[(y * ICON_SIZE + x) * 4..][..3] {
+                *channel += ((u8::MAX - *channel) as u32 * *coverage as u32 / 255) as u8;
+            }
+        }
+    }
+
+    Ok(Icon::from_rgba(rgba, ICON_SIZE as u32, ICON_SIZE as u32)?)
+}
+
+// Palettes, low bit depths and 16-bit channels are all normalized to 8-bit grey, RGB or RGBA.
+fn decode_png(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), IconError> {
+    let mut decoder = png::Decoder::new(bytes);
+
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let data = &buffer[..info.buffer_size()];
+    let rgba = match info.color_type {
+        png::ColorType::Grayscale => data.iter().flat_map(|&g| [g, g, g, u8::MAX]).collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        png::ColorType::Rgb => data
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], u8::MAX])
+            .collect(),
+        png::ColorType::Rgba => data.to_vec(),
+        png::ColorType::Indexed => return Err(IconError::Format),
+    };
+
+    Ok((info.width, info.height, rgba))
+}
+
+// Icons that are not PNGs are read as PAM (netpbm P7) with 8-bit RGB or RGBA tuples.
+fn decode_pam(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), IconError> {
+    const END: &[u8] = b"ENDHDR\n";
+
+    let header_len = bytes
+        .windows(END.len())
+        .position(|window| window == END)
+        .ok_or(IconError::Format)?;
+    let header = std::str::from_utf8(&bytes[..header_len]).map_err(|_| IconError::Format)?;
+    let data = &bytes[header_len + END.len()..];
+    let mut lines = header.lines();
+
+    if lines.next() != Some("P7") {
+        return Err(IconError::Format);
+    }
+
+    let (mut width, mut height, mut depth, mut maxval) = (0, 0, 0, 0);
+
+    for line in lines {
+        let mut fields = line.split_whitespace();
+        let key = fields.next();
+        let value = fields
+            .next()
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(0);
+
+        match key {
+            Some("WIDTH") => width = value,
+            Some("HEIGHT") => height = value,
+            Some("DEPTH") => depth = value,
+            Some("MAXVAL") => maxval = value,
+            _ => {}
+        }
+    }
+
+    let pixels = width as usize * height as usize;
+    let rgba = match (depth, maxval) {
+        (3, 255) if data.len() >= pixels * 3 => data[..pixels * 3]
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], u8::MAX])
+            .collect(),
+        (4, 255) if data.len() >= pixels * 4 => data[..pixels * 4].to_vec(),
+        _ => return Err(IconError::Format),
+    };
+
+    Ok((width, height, rgba))
+}
+
+#[derive(Debug, Error)]
+pub enum IconError {
+    #[error("Error: icons must be PNG images, or 8-bit RGB or RGBA PAM images")]
+    Format,
+    #[error("Error: {0}")]
+    Png(#[from] png::DecodingError),
+    #[error("Error: {0}")]
+    Font(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_is_decoded_to_rgba() {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+
+        encoder.set_color(png::ColorType::Rgb);
+
+        let mut writer = encoder.write_header().unwrap();
+
+        writer.write_image_data(&[255, 0, 0, 0, 0, 255]).unwrap();
+        writer.finish().unwrap();
+
+        assert!(bytes.starts_with(PNG_SIGNATURE));
+        assert_eq!(
+            decode_png(&bytes).unwrap(),
+            (2, 1, vec![255, 0, 0, 255, 0, 0, 255, 255])
+        );
+    }
+}
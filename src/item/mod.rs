@@ -1,16 +1,20 @@
 pub mod mesh;
+pub mod quads;
 pub mod texture;
 
-use mesh::Mesh;
+use cgmath::Vector2;
 use texture::Texture;
 
 pub struct Item {
-    pub mesh: Mesh,
     pub texture: Texture,
+    pub dimensions: Vector2<f32>,
 }
 
 impl Item {
-    pub fn new(mesh: Mesh, texture: Texture) -> Self {
-        Self { mesh, texture }
+    pub fn new(texture: Texture, dimensions: Vector2<f32>) -> Self {
+        Self {
+            texture,
+            dimensions,
+        }
     }
 }
@@ -0,0 +1,63 @@
+use super::{texture::Texture, Item};
+use cgmath::Vector2;
+use std::sync::Arc;
+use vulkano::device::{Device, Queue};
+
+pub const LINE_THICKNESS: f32 = 16.0;
+pub const DASH_SEGMENTS: usize = 4;
+
+pub struct Quads {
+    pub cell: Item,
+    pub line: Item,
+    pub dash: Item,
+    pub column: Item,
+    pub screen: Item,
+}
+
+impl Quads {
+    pub fn new(cell: Item, line: Item, dash: Item, column: Item, screen: Item) -> Self {
+        Self {
+            cell,
+            line,
+            dash,
+            column,
+            screen,
+        }
+    }
+
+    pub fn from_font(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        scale: f32,
+        width: f32,
+    ) -> anyhow::Result<Self> {
+        let thickness = scale / LINE_THICKNESS;
+        let cell = Self::white_rect(device.clone(), queue.clone(), Vector2::new(width, scale))?;
+        let line = Self::white_rect(
+            device.clone(),
+            queue.clone(),
+            Vector2::new(width, thickness),
+        )?;
+        let dash = Self::white_rect(
+            device.clone(),
+            queue.clone(),
+            Vector2::new(width / DASH_SEGMENTS as f32, thickness),
+        )?;
+        let column = Self::white_rect(
+            device.clone(),
+            queue.clone(),
+            Vector2::new(thickness, scale),
+        )?;
+        let screen = Self::white_rect(device, queue, Vector2::new(2.0, 2.0))?;
+
+        Ok(Self::new(cell, line, dash, column, screen))
+    }
+
+    fn white_rect(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        dimensions: Vector2<f32>,
+    ) -> anyhow::Result<Item> {
+        Ok(Item::new(Texture::white(device, queue)?, dimensions))
+    }
+}
@@ -0,0 +1,17 @@
+#[cfg(feature = "renderer")]
+pub mod dropdown;
+#[cfg(feature = "renderer")]
+pub mod icon;
+#[cfg(feature = "renderer")]
+pub mod item;
+pub mod loaded_font;
+#[cfg(feature = "renderer")]
+pub mod renderer;
+#[cfg(feature = "renderer")]
+pub mod shaders;
+pub mod terminal;
+
+pub use terminal::{pty::Pty, Performer, Terminal};
+
+pub const APP_NAME: &str = "foxterm";
+pub const SCALE: f32 = 1.0 / 1000.0;
@@ -1,58 +1,103 @@
-use crate::{
-    item::{mesh::Mesh, texture::Texture, Item},
-    SCALE,
-};
+#[cfg(feature = "renderer")]
+use crate::item::{texture::Texture, Item};
+use crate::SCALE;
 use cgmath::Vector2;
 use fontdue::Metrics;
-use std::sync::Arc;
-use vulkano::{device::Device, device::Queue, format::Format, image::ImageDimensions};
+#[cfg(feature = "renderer")]
+use std::sync::{Arc, OnceLock};
+#[cfg(feature = "renderer")]
+use vulkano::{device::Queue, format::Format, image::ImageDimensions};
 
+// Coverage, one byte per pixel, or RGBA for subpixel glyphs.
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+    pub subpixel: bool,
+}
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize, data: Vec<u8>, subpixel: bool) -> Self {
+        Self {
+            width,
+            height,
+            data,
+            subpixel,
+        }
+    }
+}
+
+// Glyphs only hold their bitmap, the texture is made the first time a renderer draws one.
 pub struct Chr {
-    pub id: u8,
+    pub id: char,
     pub dimensions: Vector2<f32>,
     pub bearing: Vector2<f32>,
-    pub item: Item,
+    pub bitmap: Bitmap,
+    #[cfg(feature = "renderer")]
+    item: OnceLock<Item>,
 }
 
 impl Chr {
-    pub fn new(id: u8, dimensions: Vector2<f32>, bearing: Vector2<f32>, item: Item) -> Self {
+    pub fn new(id: char, dimensions: Vector2<f32>, bearing: Vector2<f32>, bitmap: Bitmap) -> Self {
         Self {
             id,
             dimensions,
             bearing,
-            item,
+            bitmap,
+            #[cfg(feature = "renderer")]
+            item: OnceLock::new(),
         }
     }
 
+    pub fn offset(&self) -> Vector2<f32> {
+        Vector2::new(self.bearing.x, -self.bearing.y)
+    }
+
+    // Fills the cell edge to edge, whatever the glyph's own metrics.
+    pub fn stretched(self, cell: Vector2<f32>) -> Self {
+        Self::new(self.id, cell, Vector2::new(0.0, 0.0), self.bitmap)
+    }
+
     pub fn from_bitmap(
-        id: u8,
-        device: Arc<Device>,
-        queue: Arc<Queue>,
+        id: char,
         metrics: &Metrics,
-        bitmap: &[u8],
-    ) -> anyhow::Result<Self> {
-        let dimensions = Vector2::new(metrics.width as f32, metrics.height as f32) * SCALE;
-        let bearing = Vector2::new(metrics.xmin as f32, metrics.ymin as f32) * SCALE;
-        let mesh = Mesh::from_rect(queue.clone(), dimensions)?;
-        let texture = Self::create_texture(device, queue, metrics, bitmap)?;
-        let item = Item::new(mesh, texture);
-
-        Ok(Self::new(id, dimensions, bearing, item))
+        bitmap: Vec<u8>,
+        subpixel: bool,
+        scale_factor: f32,
+    ) -> Self {
+        let scale = SCALE / scale_factor;
+        let dimensions = Vector2::new(metrics.width as f32, metrics.height as f32) * scale;
+        let bearing = Vector2::new(metrics.xmin as f32, metrics.ymin as f32) * scale;
+        let bitmap = Bitmap::new(metrics.width, metrics.height, bitmap, subpixel);
+
+        Self::new(id, dimensions, bearing, bitmap)
     }
 
-    fn create_texture(
-        device: Arc<Device>,
-        queue: Arc<Queue>,
-        metrics: &Metrics,
-        bitmap: &[u8],
-    ) -> anyhow::Result<Texture> {
-        let dims = ImageDimensions::Dim2d {
-            width: metrics.width as u32,
-            height: metrics.height as u32,
-            array_layers: 1,
+    #[cfg(feature = "renderer")]
+    pub fn item(&self, queue: Arc<Queue>) -> anyhow::Result<&Item> {
+        if let Some(item) = self.item.get() {
+            return Ok(item);
+        }
+
+        let format = if self.bitmap.subpixel {
+            Format::R8G8B8A8_UNORM
+        } else {
+            Format::R8_UNORM
         };
-        let texture = Texture::from_data(device, queue, Format::R8_SRGB, dims, bitmap)?;
+        let texture = Texture::from_data(
+            queue.device().clone(),
+            queue,
+            format,
+            ImageDimensions::Dim2d {
+                width: self.bitmap.width as u32,
+                height: self.bitmap.height as u32,
+                array_layers: 1,
+            },
+            &self.bitmap.data,
+        )?;
 
-        Ok(texture)
+        Ok(self
+            .item
+            .get_or_init(|| Item::new(texture, self.dimensions)))
     }
 }
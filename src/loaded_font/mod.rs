@@ -1,45 +1,181 @@
 pub mod chr;
 
-use crate::{terminal::config::Config, SCALE};
+use crate::{
+    terminal::config::{Config, Font as FontConfig, SubpixelOrder},
+    SCALE,
+};
+use cgmath::Vector2;
 use chr::Chr;
 use fontdue::{Font, FontSettings};
-use std::{fs::File, io::Read, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    ops::RangeInclusive,
+    sync::{Arc, RwLock},
+};
 use thiserror::Error;
-use vulkano::{device::Device, device::Queue};
+use unicode_width::UnicodeWidthChar;
+
+pub const FALLBACK_FONT: &[u8] = include_bytes!("../../test.ttf");
+
+// Powerline separators are drawn to meet their neighbours without a seam.
+pub const POWERLINE_RANGE: RangeInclusive<char> = '\u{e0b0}'..='\u{e0d4}';
+
+pub struct RangedFont {
+    pub font: Font,
+    pub ranges: Vec<(u32, u32)>,
+}
+
+impl RangedFont {
+    pub fn new(font: Font, ranges: Vec<(u32, u32)>) -> Self {
+        Self { font, ranges }
+    }
+
+    pub fn covers(&self, c: char) -> bool {
+        self.ranges
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&(c as u32)))
+            && self.font.lookup_glyph_index(c) != 0
+    }
+}
+
+// What is needed to rasterize a glyph the first time it is asked for.
+pub struct Rasterizer {
+    pub config: FontConfig,
+    pub font: Font,
+    pub ranged: Vec<RangedFont>,
+}
+
+impl Rasterizer {
+    pub fn new(config: FontConfig, font: Font, ranged: Vec<RangedFont>) -> Self {
+        Self {
+            config,
+            font,
+            ranged,
+        }
+    }
+}
 
 pub struct LoadedFont {
-    pub chrs: Vec<Arc<Chr>>,
+    pub chrs: RwLock<HashMap<char, Arc<Chr>>>,
+    pub rasterizer: Option<Rasterizer>,
     pub scale: f32,
+    pub cell_width: f32,
+    pub scale_factor: f32,
 }
 
 impl LoadedFont {
-    pub fn new(chrs: Vec<Arc<Chr>>, scale: f32) -> Self {
-        Self { chrs, scale }
+    pub fn new(
+        chrs: HashMap<char, Arc<Chr>>,
+        rasterizer: Option<Rasterizer>,
+        scale: f32,
+        cell_width: f32,
+        scale_factor: f32,
+    ) -> Self {
+        Self {
+            chrs: RwLock::new(chrs),
+            rasterizer,
+            scale,
+            cell_width,
+            scale_factor,
+        }
     }
 
-    pub fn from_file(
-        device: Arc<Device>,
-        queue: Arc<Queue>,
-        config: &Config,
-    ) -> anyhow::Result<Self> {
-        let bytes = Self::load_bytes(&config.font.path)?;
-        let font = Self::try_font_from_fontdue_result(Font::from_bytes(
-            bytes.as_slice(),
-            FontSettings::default(),
-        ))?;
-        let chrs = Self::create_chrs(device, queue, &font, config.font.scale);
+    // Glyphs are rasterized at the monitor's scale factor and keep the same size on screen, so
+    // they stay sharp on high density displays. Only ASCII is rasterized up front, anything else
+    // the first time it is printed, so large ranges cost nothing until they are used.
+    pub fn from_file(config: &Config, scale_factor: f32) -> anyhow::Result<Self> {
+        let mut chrs = HashMap::new();
+        let ranged = config
+            .fonts
+            .iter()
+            .filter_map(|entry| match Self::load_font(&entry.path) {
+                Ok(font) => Some(RangedFont::new(font, entry.ranges.clone())),
+                Err(e) => {
+                    println!("Error loading font {}: {:?}", entry.path, e);
+
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let font = match Self::load_font(&config.font.path) {
+            Ok(font) => font,
+            Err(e) => {
+                println!(
+                    "Error loading font {}: {:?}, falling back to the built-in font",
+                    config.font.path, e
+                );
+
+                Self::font_from_bytes(FALLBACK_FONT)?
+            }
+        };
+
+        let cell_width = cell_width(config, Some(&font));
 
-        Ok(Self::new(chrs, config.font.scale * SCALE))
+        Self::create_chrs(
+            &font,
+            ('!'..='~').filter(|c| !ranged.iter().any(|ranged| ranged.covers(*c))),
+            &config.font,
+            scale_factor,
+            cell_width,
+            &mut chrs,
+        );
+
+        let rasterizer = Rasterizer::new(config.font.clone(), font, ranged);
+
+        Ok(Self::new(
+            chrs,
+            Some(rasterizer),
+            config.font.scale * SCALE,
+            cell_width,
+            scale_factor,
+        ))
     }
 
-    pub fn get_chr_by_id(&self, id: u8) -> Option<Arc<Chr>> {
-        if id >= 33 {
-            let i = id as usize - 33;
+    pub fn cell_width(&self) -> f32 {
+        self.cell_width
+    }
 
-            self.chrs.get(i).cloned()
-        } else {
-            None
+    // Ranged fonts take precedence over the main font, and over each other in the order they are
+    // configured.
+    pub fn get_chr(&self, c: char) -> Option<Arc<Chr>> {
+        if let Some(chr) = self.chrs.read().unwrap().get(&c) {
+            return Some(chr.clone());
         }
+
+        let rasterizer = self.rasterizer.as_ref()?;
+        let font = match rasterizer.ranged.iter().find(|ranged| ranged.covers(c)) {
+            Some(ranged) => &ranged.font,
+            None if rasterizer.font.lookup_glyph_index(c) != 0 => &rasterizer.font,
+            None => return None,
+        };
+        let mut chrs = self.chrs.write().unwrap();
+
+        Self::create_chrs(
+            font,
+            [c].into_iter(),
+            &rasterizer.config,
+            self.scale_factor,
+            self.cell_width,
+            &mut chrs,
+        );
+
+        chrs.get(&c).cloned()
+    }
+
+    // Anything that takes no column of its own, like combining marks in every script, is drawn
+    // onto the previous cell.
+    pub fn is_combining(c: char) -> bool {
+        c.width() == Some(0)
+    }
+
+    fn load_font(path: &String) -> anyhow::Result<Font> {
+        Self::font_from_bytes(&Self::load_bytes(path)?)
+    }
+
+    fn font_from_bytes(bytes: &[u8]) -> anyhow::Result<Font> {
+        Self::try_font_from_fontdue_result(Font::from_bytes(bytes, FontSettings::default()))
     }
 
     fn load_bytes(path: &String) -> anyhow::Result<Vec<u8>> {
@@ -59,33 +195,123 @@ impl LoadedFont {
     }
 
     fn create_chrs(
-        device: Arc<Device>,
-        queue: Arc<Queue>,
         font: &Font,
-        scale: f32,
-    ) -> Vec<Arc<Chr>> {
-        (33..=126_u8)
-            .filter_map(|i| {
-                let c = i as char;
-                let (metrics, bitmap) = font.rasterize(c, scale);
-
-                match Chr::from_bitmap(i, device.clone(), queue.clone(), &metrics, &bitmap) {
-                    Ok(chr) => Some(Arc::new(chr)),
-                    Err(_) => None,
+        chars: impl Iterator<Item = char>,
+        config: &FontConfig,
+        scale_factor: f32,
+        cell_width: f32,
+        chrs: &mut HashMap<char, Arc<Chr>>,
+    ) {
+        let created = chars
+            .filter(|c| !chrs.contains_key(c))
+            .filter_map(|c| {
+                let (metrics, mut bitmap) = if config.subpixel {
+                    let (metrics, bitmap) = font.rasterize_subpixel(c, config.scale * scale_factor);
+
+                    (metrics, Self::subpixel_rgba(&bitmap, config.subpixel_order))
+                } else {
+                    font.rasterize(c, config.scale * scale_factor)
+                };
+
+                if !config.antialias {
+                    for coverage in &mut bitmap {
+                        *coverage = if *coverage >= u8::MAX / 2 { u8::MAX } else { 0 };
+                    }
+                }
+
+                // Empty glyphs have nothing to draw, they are printed as blank cells.
+                if metrics.width == 0 || metrics.height == 0 {
+                    return None;
+                }
+
+                let chr = Chr::from_bitmap(c, &metrics, bitmap, config.subpixel, scale_factor);
+
+                if config.powerline_stretch && POWERLINE_RANGE.contains(&c) {
+                    let cell = Vector2::new(cell_width, config.scale * SCALE);
+
+                    Some((c, Arc::new(chr.stretched(cell))))
+                } else {
+                    Some((c, Arc::new(chr)))
                 }
             })
+            .collect::<Vec<_>>();
+
+        chrs.extend(created);
+    }
+
+    // Three-channel formats are rarely sampleable, so the coverage is padded out to RGBA.
+    fn subpixel_rgba(bitmap: &[u8], order: SubpixelOrder) -> Vec<u8> {
+        bitmap
+            .chunks_exact(3)
+            .flat_map(|rgb| match order {
+                SubpixelOrder::Rgb => [rgb[0], rgb[1], rgb[2], u8::MAX],
+                SubpixelOrder::Bgr => [rgb[2], rgb[1], rgb[0], u8::MAX],
+            })
             .collect()
     }
 }
 
 impl Default for LoadedFont {
     fn default() -> Self {
-        Self::new(Vec::new(), 0.0)
+        Self::new(HashMap::new(), None, 0.0, 0.0, 1.0)
     }
 }
 
+// Every column is this wide, whatever each glyph's own advance, so long lines stay on the grid.
+// A configured width wins over the advance of M or 0 in the primary font. Without a font, like
+// before one is loaded, half the line height stands in.
+pub fn cell_width(config: &Config, font: Option<&Font>) -> f32 {
+    let advance = font.and_then(|font| {
+        ['M', '0']
+            .into_iter()
+            .filter(|c| font.lookup_glyph_index(*c) != 0)
+            .map(|c| font.metrics(c, config.font.scale).advance_width)
+            .find(|advance| *advance > 0.0)
+    });
+
+    config
+        .cell_width
+        .or(advance)
+        .unwrap_or(config.font.scale / 2.0)
+        * SCALE
+}
+
 #[derive(Debug, Error)]
 pub enum LoadedFontError {
     #[error("Error: {0}")]
     StrError(&'static str),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_width_follows_the_font() {
+        let font = LoadedFont::font_from_bytes(FALLBACK_FONT).unwrap();
+        let config = Config::default();
+        let advance = font.metrics('M', config.font.scale).advance_width * SCALE;
+
+        assert_eq!(cell_width(&config, Some(&font)), advance);
+        assert_eq!(cell_width(&config, None), config.font.scale / 2.0 * SCALE);
+        assert_eq!(
+            cell_width(
+                &Config {
+                    cell_width: Some(30.0),
+                    ..Config::default()
+                },
+                Some(&font)
+            ),
+            30.0 * SCALE
+        );
+    }
+
+    #[test]
+    fn zero_width_characters_combine() {
+        assert!(LoadedFont::is_combining('\u{301}'));
+        assert!(LoadedFont::is_combining('\u{5b4}'));
+        assert!(LoadedFont::is_combining('\u{94d}'));
+        assert!(!LoadedFont::is_combining('a'));
+        assert!(!LoadedFont::is_combining('\u{4e00}'));
+    }
+}
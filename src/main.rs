@@ -1,20 +1,55 @@
-mod item;
-mod loaded_font;
-mod renderer;
-mod shaders;
-mod terminal;
+mod args;
 
-use renderer::Renderer;
-use terminal::Terminal;
-
-pub const APP_NAME: &str = "foxterm";
-pub const SCALE: f32 = 1.0 / 1000.0;
+use args::Args;
+use foxterm::{renderer::Renderer, terminal::config::Config, Terminal};
+use std::process;
 
 fn main() {
-    let terminal = match Terminal::init().unwrap() {
-        Some(terminal) => terminal,
-        None => return,
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if args.list_gpus {
+        if let Err(e) = Renderer::list_gpus() {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    if args.dump_config {
+        match Config::dump_default_file() {
+            Ok(dump) => print!("{}", dump),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+
+        return;
+    }
+
+    let mut terminal = match Terminal::init(
+        &args.command,
+        args.working_directory.as_deref(),
+        args.replay.clone().map(|path| (path, args.replay_rate)),
+    ) {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
     };
 
-    Renderer::init(terminal).unwrap();
+    terminal.config.vulkan_validation |= args.vulkan_validation;
+
+    if let Err(e) = Renderer::init(terminal) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
@@ -1,16 +1,33 @@
 use crate::{
+    dropdown, icon,
     item::{
         mesh::{Mesh, Vertex},
-        texture::Texture,
+        quads::{Quads, DASH_SEGMENTS, LINE_THICKNESS},
         Item,
     },
     loaded_font::LoadedFont,
     shaders::{fragment, vertex, Shaders},
-    terminal::{drawable::RenderItem, Performer, Terminal},
+    terminal::{
+        color::Color,
+        config::{
+            BellMode, Config, CursorColor, CursorStyle, ExportFormat, KeyBinding, PresentMode,
+        },
+        drawable::{RenderItem, Underline},
+        grid_size, hyperlink,
+        mouse::{self, Mouse},
+        notify, Performer, Terminal,
+    },
     APP_NAME,
 };
-use cgmath::{Matrix4, Vector2};
-use std::sync::Arc;
+use cgmath::{InnerSpace, Matrix4, Vector2, Vector3, Vector4, VectorSpace};
+use crossbeam::channel::Sender;
+use std::{
+    collections::HashMap,
+    fs, process,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
 use vulkano::{
     buffer::{cpu_pool::CpuBufferPool, BufferUsage, TypedBufferAccess},
     command_buffer::{
@@ -20,17 +37,16 @@ use vulkano::{
     descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
     device::{
         physical::{PhysicalDevice, PhysicalDeviceType},
-        Device, DeviceCreateInfo, DeviceExtensions, QueueCreateInfo,
+        Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo,
     },
-    format::Format,
-    image::{
-        attachment::AttachmentImage, view::ImageView, ImageAccess, ImageUsage, SwapchainImage,
+    image::{view::ImageView, ImageAccess, ImageUsage, SwapchainImage},
+    instance::{
+        debug::{DebugCallback, MessageSeverity, MessageType},
+        layers_list, Instance, InstanceCreateInfo, InstanceExtensions,
     },
-    instance::{Instance, InstanceCreateInfo},
     pipeline::{
         graphics::{
-            color_blend::ColorBlendState,
-            depth_stencil::DepthStencilState,
+            color_blend::{AttachmentBlend, BlendFactor, ColorBlendState},
             input_assembly::{InputAssemblyState, PrimitiveTopology},
             vertex_input::BuffersDefinition,
             viewport::{Viewport, ViewportState},
@@ -39,31 +55,57 @@ use vulkano::{
         Pipeline, PipelineBindPoint,
     },
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
-    swapchain::{self, AcquireError, Swapchain, SwapchainCreateInfo, SwapchainCreationError},
+    swapchain::{
+        self, AcquireError, CompositeAlpha, SupportedCompositeAlpha, Swapchain,
+        SwapchainCreateInfo, SwapchainCreationError,
+    },
     sync::{self, FlushError, GpuFuture},
 };
 use vulkano_win::VkSurfaceBuild;
 use winit::{
-    event::{Event, WindowEvent},
+    event::{ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    window::{Icon, UserAttentionType, Window, WindowBuilder},
 };
 use winit_input_helper::WinitInputHelper;
 
+#[cfg(all(unix, not(target_os = "macos")))]
+use winit::platform::unix::WindowBuilderExtUnix;
+
+pub const CLOSE_PROMPT: &str = "A process is still running. Close anyway? [y/n]";
+pub const CURSOR_EASING: f32 = 0.35;
+pub const CURSOR_SNAP_DISTANCE: f32 = 0.5;
+pub const STEM_DARKENING: f32 = 0.3;
+pub const DEBUG_CELL_ALPHA: f32 = 0.08;
+pub const ACTIVE_OUTPUT: Duration = Duration::from_millis(500);
+pub const MIN_CONTRAST: f32 = 0.3;
+pub const CONTRAST_STEPS: usize = 8;
+pub const TAB_MARKERS: [char; 2] = ['→', '>'];
+pub const SPACE_MARKERS: [char; 2] = ['·', '.'];
+pub const DIM_BRIGHTNESS: f32 = 0.3;
+pub const DIM_FADE: Duration = Duration::from_secs(2);
+pub const DIMMED_POLL: Duration = Duration::from_millis(250);
+pub const FRAME_RETRY: Duration = Duration::from_millis(50);
+pub const FRAME_RETRY_MAX: Duration = Duration::from_secs(2);
+pub const FRAME_FAILURE_BUDGET: Duration = Duration::from_secs(30);
+pub const ZOOM_STEP: f32 = 1.1;
+pub const MIN_FONT_SCALE: f32 = 8.0;
+pub const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
 pub struct Renderer;
 
 impl Renderer {
-    pub fn init(terminal: Terminal) -> anyhow::Result<()> {
+    pub fn init(mut terminal: Terminal) -> anyhow::Result<()> {
         let proj = cgmath::ortho::<f32>(-1.0, 1.0, -1.0, 1.0, -1.0, 1.0);
-        let required_extensions = vulkano_win::required_extensions();
-        let instance = Instance::new(InstanceCreateInfo {
-            enabled_extensions: required_extensions,
-            ..Default::default()
-        })?;
+        let (instance, debug_callback) = Self::instance(terminal.config.vulkan_validation)?;
         let event_loop = EventLoop::new();
-        let surface = WindowBuilder::new()
-            .with_title(APP_NAME)
-            .build_vk_surface(&event_loop, instance.clone())?;
+        let surface =
+            Self::window_builder(&terminal).build_vk_surface(&event_loop, instance.clone())?;
+
+        if terminal.config.dropdown {
+            dropdown::place(surface.window());
+            dropdown::listen(event_loop.create_proxy())?;
+        }
         let device_extensions = DeviceExtensions {
             khr_swapchain: true,
             ..DeviceExtensions::none()
@@ -73,7 +115,9 @@ impl Renderer {
 
             match terminal.config.device_index {
                 Some(physical_index) => {
-                    let device = devices.nth(physical_index).unwrap();
+                    let device = devices
+                        .nth(physical_index)
+                        .ok_or(RendererError::NoSuchDevice(physical_index))?;
 
                     device
                         .queue_families()
@@ -81,7 +125,7 @@ impl Renderer {
                             q.supports_graphics() && q.supports_surface(&surface).unwrap_or(false)
                         })
                         .map(|q| (device, q))
-                        .unwrap()
+                        .ok_or(RendererError::UnsupportedDevice(physical_index))?
                 }
                 None => devices
                     .filter(|&p| p.supported_extensions().is_superset_of(&device_extensions))
@@ -100,7 +144,7 @@ impl Renderer {
                         PhysicalDeviceType::Cpu => 3,
                         PhysicalDeviceType::Other => 4,
                     })
-                    .unwrap(),
+                    .ok_or(RendererError::NoDevice)?,
             }
         };
         let (device, mut queues) = Device::new(
@@ -115,6 +159,9 @@ impl Renderer {
         )?;
         let shaders = Arc::new(Shaders::new(device.clone())?);
         let queue = queues.next().unwrap();
+        let present_modes = physical_device
+            .surface_present_modes(&surface)?
+            .collect::<Vec<_>>();
         let (mut swapchain, images) = {
             let surface_capabilities =
                 physical_device.surface_capabilities(&surface, Default::default())?;
@@ -129,11 +176,15 @@ impl Renderer {
                     image_format,
                     image_extent: surface.window().inner_size().into(),
                     image_usage: ImageUsage::color_attachment(),
-                    composite_alpha: surface_capabilities
-                        .supported_composite_alpha
-                        .iter()
-                        .next()
-                        .unwrap(),
+                    composite_alpha: Self::composite_alpha(
+                        &surface_capabilities.supported_composite_alpha,
+                        terminal.config.bg_color[3] < 1.0,
+                    ),
+                    present_mode: Self::present_mode(
+                        terminal.config.present_mode,
+                        &present_modes,
+                        false,
+                    ),
                     ..Default::default()
                 },
             )?
@@ -145,18 +196,12 @@ impl Renderer {
                     store: DontCare,
                     format: swapchain.image_format(),
                     samples: 1,
-                },
-                depth: {
-                    load: Clear,
-                    store: DontCare,
-                    format: Format::D16_UNORM,
-                    samples: 1,
                 }
             },
             pass:
             {
                 color: [color],
-                depth_stencil: {depth}
+                depth_stencil: {}
             }
         )?;
         let (mut pipeline, mut framebuffers) = Self::window_size_dependent_setup(
@@ -169,60 +214,368 @@ impl Renderer {
             CpuBufferPool::<vertex::ty::Data>::new(device.clone(), BufferUsage::uniform_buffer());
         let frag_uniform_buffer =
             CpuBufferPool::<fragment::ty::Data>::new(device.clone(), BufferUsage::uniform_buffer());
+        let quad = Mesh::from_rect(queue.clone(), Vector2::new(1.0, 1.0))?;
         let font = Arc::new(LoadedFont::from_file(
-            device.clone(),
-            queue.clone(),
             &terminal.config,
+            surface.window().scale_factor() as f32,
         )?);
-        let cursor = Item::new(
-            Mesh::from_rect(queue.clone(), Vector2::new(font.scale / 2.0, font.scale))?,
-            Texture::white(device.clone(), queue.clone())?,
-        );
-        let performer = terminal.spawn_reader(font);
+        let mut quads =
+            Quads::from_font(device.clone(), queue.clone(), font.scale, font.cell_width())?;
         let write_sndr = terminal.spawn_writer();
+        let performer =
+            terminal.spawn_reader(font, write_sndr.clone(), surface.window().inner_size());
         let mut input = WinitInputHelper::new();
         let mut recreate_swapchain = false;
+        let mut frame_failures = FrameFailures::default();
+        let mut confirming_close = false;
+        let mut cursor_pos = performer.read().unwrap().pos;
+        let mut title = APP_NAME.to_owned();
+        let mut focused = true;
+        let mut mouse = Mouse::default();
+        let started = Instant::now();
+        let mut visible = true;
+        let mut urgent = false;
+        let mut last_input = Instant::now();
+        // Zooming changes the live font scale, this is what reset_font_size_binding goes back to.
+        let mut configured_scale = terminal.config.font.scale;
         let mut previous_frame_end = Some(sync::now(device.clone()).boxed());
 
         event_loop.run(move |event, _, control_flow| {
-            input.update(&event);
+            // Validation messages are reported for as long as the callback is alive.
+            let _ = &debug_callback;
+
+            if let Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput { .. }
+                    | WindowEvent::MouseInput { .. }
+                    | WindowEvent::MouseWheel { .. }
+                    | WindowEvent::CursorMoved { .. },
+                ..
+            } = event
+            {
+                last_input = Instant::now();
+            }
+
+            // Keys are handled as soon as winit has delivered a step's events, rather than waiting
+            // for the next redraw.
+            if input.update(&event) {
+                if confirming_close {
+                    if input.key_pressed(VirtualKeyCode::Y) {
+                        *control_flow = ControlFlow::Exit;
+
+                        return;
+                    } else if input.key_pressed(VirtualKeyCode::N)
+                        || input.key_pressed(VirtualKeyCode::Escape)
+                    {
+                        confirming_close = false;
+                    }
+                } else if terminal.config.reload_binding.pressed(&input) {
+                    match Self::reload_config(
+                        device.clone(),
+                        queue.clone(),
+                        &mut terminal,
+                        &performer,
+                        &mut quads,
+                    ) {
+                        Ok(()) => configured_scale = terminal.config.font.scale,
+                        Err(e) => println!("Error on config reload: {:?}", e),
+                    }
+                } else if let Some(scale) = Self::zoom(&terminal.config, &input, configured_scale) {
+                    if let Err(e) = Self::set_font_scale(
+                        device.clone(),
+                        queue.clone(),
+                        &mut terminal,
+                        &performer,
+                        &mut quads,
+                        scale,
+                    ) {
+                        println!("Error changing the font size: {:?}", e);
+                    }
+                } else if terminal.config.reset_binding.pressed(&input) {
+                    performer.write().unwrap().reset();
+                } else if terminal.config.debug_binding.pressed(&input) {
+                    // Kept off stdout, which may be piped to whatever foxterm was started from.
+                    eprintln!("{}", performer.read().unwrap().state());
+                } else if terminal.config.dropdown && terminal.config.hide_binding.pressed(&input) {
+                    visible = false;
+
+                    surface.window().set_visible(false);
+                } else if terminal.config.export_binding.pressed(&input) {
+                    let performer = performer.read().unwrap();
+                    let export = match terminal.config.export_format {
+                        ExportFormat::Ansi => performer.export_ansi(),
+                        ExportFormat::Html => {
+                            let (fg, bg) = Self::default_colors(&terminal.config, &performer);
+
+                            performer.export_html(fg, bg, terminal.config.bold_is_bright)
+                        }
+                    };
+                    let path = shellexpand::tilde(&terminal.config.export_path).into_owned();
+
+                    if let Err(e) = fs::write(&path, export) {
+                        println!("Error exporting the screen to {}: {:?}", path, e);
+                    }
+                } else {
+                    terminal
+                        .update_pty(&write_sndr, &input, &performer.read().unwrap())
+                        .unwrap();
+                }
+            }
 
             match event {
                 Event::WindowEvent {
                     event: WindowEvent::CloseRequested,
                     ..
-                } => *control_flow = ControlFlow::Exit,
+                } => {
+                    if terminal.config.confirm_close && terminal.pty.child_alive() {
+                        confirming_close = true;
+                    } else {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+                Event::LoopDestroyed => {
+                    terminal.shutdown();
+
+                    if frame_failures.fatal {
+                        process::exit(1);
+                    }
+
+                    // The child's exit status becomes foxterm's, closing the window exits with 0.
+                    process::exit(terminal.pty.exit_code().unwrap_or(0));
+                }
+                Event::UserEvent(()) => {
+                    visible = !visible;
+
+                    surface.window().set_visible(visible);
+
+                    if visible {
+                        surface.window().focus_window();
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::Resized(size),
+                    ..
+                } => {
+                    performer.write().unwrap().window_size = size;
+                    recreate_swapchain = true;
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::ScaleFactorChanged { scale_factor, .. },
+                    ..
+                } => {
+                    if let Err(e) = Self::load_font(
+                        device.clone(),
+                        queue.clone(),
+                        &terminal.config,
+                        &mut performer.write().unwrap(),
+                        &mut quads,
+                        scale_factor as f32,
+                    ) {
+                        println!("Error rasterizing for the new scale factor: {:?}", e);
+                    }
+
+                    recreate_swapchain = true;
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::Focused(value),
+                    ..
+                } => {
+                    focused = value;
+
+                    // Activity and the bell are what happened while the window was unattended.
+                    if focused {
+                        let mut performer = performer.write().unwrap();
+
+                        performer.activity = false;
+                        performer.bell = false;
+                        urgent = false;
+                    }
+                }
                 Event::WindowEvent {
-                    event: WindowEvent::Resized(_),
+                    event: WindowEvent::CursorMoved { position, .. },
                     ..
-                } => recreate_swapchain = true,
+                } => {
+                    let performer = performer.read().unwrap();
+                    let cell = performer.cell_at(position.x, position.y);
+
+                    Self::report_mouse(
+                        &write_sndr,
+                        mouse.moved(cell, Self::mouse_modifiers(&input), &performer.modes),
+                    );
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::MouseInput { state, button, .. },
+                    ..
+                } => {
+                    let button = match button {
+                        MouseButton::Left => Some(0),
+                        MouseButton::Middle => Some(1),
+                        MouseButton::Right => Some(2),
+                        MouseButton::Other(_) => None,
+                    };
+
+                    // Ctrl+click opens hyperlinks, neither the press nor the release is reported.
+                    if button == Some(0) && input.held_control() {
+                        if state == ElementState::Pressed {
+                            let (col, row) = mouse.cell;
+                            let text = performer.read().unwrap().row_text(row);
+
+                            if let Err(e) =
+                                hyperlink::open(&terminal.config.hyperlink_rules, &text, col)
+                            {
+                                println!("Error opening hyperlink: {:?}", e);
+                            }
+                        }
+                    } else if let Some(button) = button {
+                        Self::report_mouse(
+                            &write_sndr,
+                            mouse.button(
+                                button,
+                                state == ElementState::Pressed,
+                                Self::mouse_modifiers(&input),
+                                &performer.read().unwrap().modes,
+                            ),
+                        );
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::MouseWheel { delta, .. },
+                    ..
+                } => {
+                    let y = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y as f64,
+                        MouseScrollDelta::PixelDelta(position) => position.y,
+                    };
+
+                    if y != 0.0 {
+                        Self::report_mouse(
+                            &write_sndr,
+                            mouse.wheel(
+                                y > 0.0,
+                                Self::mouse_modifiers(&input),
+                                &performer.read().unwrap().modes,
+                            ),
+                        );
+                    }
+                }
                 Event::RedrawEventsCleared => {
-                    terminal.update_pty(&write_sndr, &input).unwrap();
+                    if terminal.pty.exit_code().is_some() {
+                        *control_flow = ControlFlow::Exit;
+                    }
+
+                    let idle = performer
+                        .read()
+                        .unwrap()
+                        .last_output
+                        .map_or(last_input.elapsed(), |output| {
+                            output.elapsed().min(last_input.elapsed())
+                        });
+                    let dim = Self::dim(terminal.config.dim_after_secs, idle);
+
+                    if *control_flow != ControlFlow::Exit {
+                        // Once fully dimmed, frames are only drawn often enough to notice output.
+                        *control_flow = if dim >= 1.0 {
+                            ControlFlow::WaitUntil(Instant::now() + DIMMED_POLL)
+                        } else {
+                            Self::control_flow(focused, terminal.config.unfocused_fps)
+                        };
+                    }
+
+                    {
+                        let performer = performer.read().unwrap();
+
+                        // A bell while unattended is reported once, until the window is focused again.
+                        if !focused && performer.bell && !urgent {
+                            urgent = true;
+
+                            match terminal.config.bell_mode {
+                                BellMode::Attention => surface
+                                    .window()
+                                    .request_user_attention(Some(UserAttentionType::Informational)),
+                                BellMode::Notify => {
+                                    notify::send(&format!("{}: bell", APP_NAME), &performer.title)
+                                }
+                                BellMode::None => {}
+                            }
+                        }
+
+                        if performer.title != title {
+                            title = performer.title.clone();
+
+                            surface.window().set_title(&title);
+                        }
+                    }
+
+                    cursor_pos = Self::animate_cursor(
+                        cursor_pos,
+                        performer.read().unwrap().pos,
+                        terminal.config.cursor_animation,
+                    );
+
+                    // A minimized window has no extent to render to, the reader keeps running and
+                    // the next resize recreates the swapchain.
+                    let extent = surface.window().inner_size();
+
+                    if extent.width == 0 || extent.height == 0 {
+                        return;
+                    }
+
+                    if frame_failures.waiting(control_flow) {
+                        return;
+                    }
 
                     previous_frame_end.as_mut().unwrap().cleanup_finished();
 
+                    let present_mode = Self::present_mode(
+                        terminal.config.present_mode,
+                        &present_modes,
+                        performer
+                            .read()
+                            .unwrap()
+                            .last_output
+                            .is_some_and(|output| output.elapsed() < ACTIVE_OUTPUT),
+                    );
+
+                    if present_mode != swapchain.present_mode() {
+                        recreate_swapchain = true;
+                    }
+
                     if recreate_swapchain {
                         let (new_swapchain, images) =
                             match swapchain.recreate(SwapchainCreateInfo {
                                 image_extent: surface.window().inner_size().into(),
+                                present_mode,
                                 ..swapchain.create_info()
                             }) {
                                 Ok(r) => r,
                                 Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => {
                                     return
                                 }
-                                Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
+                                Err(e) => {
+                                    println!("Failed to recreate swapchain: {:?}", e);
+                                    frame_failures.failed(control_flow);
+
+                                    return;
+                                }
                             };
 
                         swapchain = new_swapchain;
 
-                        let (new_pipeline, new_framebuffers) = Self::window_size_dependent_setup(
-                            render_pass.clone(),
-                            device.clone(),
-                            shaders.clone(),
-                            &images,
-                        )
-                        .unwrap();
+                        let (new_pipeline, new_framebuffers) =
+                            match Self::window_size_dependent_setup(
+                                render_pass.clone(),
+                                device.clone(),
+                                shaders.clone(),
+                                &images,
+                            ) {
+                                Ok(r) => r,
+                                Err(e) => {
+                                    println!("Failed to recreate framebuffers: {:?}", e);
+                                    frame_failures.failed(control_flow);
+
+                                    return;
+                                }
+                            };
 
                         pipeline = new_pipeline;
                         framebuffers = new_framebuffers;
@@ -239,53 +592,151 @@ impl Renderer {
                                 return;
                             }
 
-                            Err(e) => panic!("Failed to acquire next image: {:?}", e),
+                            Err(e) => {
+                                println!("Failed to acquire next image: {:?}", e);
+
+                                recreate_swapchain = true;
+                                frame_failures.failed(control_flow);
+
+                                return;
+                            }
                         };
 
                     if suboptimal {
                         recreate_swapchain = true;
                     }
 
-                    let mut builder = AutoCommandBufferBuilder::primary(
+                    let (_, clear_color) =
+                        Self::default_colors(&terminal.config, &performer.read().unwrap());
+                    let mut builder = match AutoCommandBufferBuilder::primary(
                         device.clone(),
                         queue.family(),
                         CommandBufferUsage::OneTimeSubmit,
-                    )
-                    .unwrap();
+                    ) {
+                        Ok(builder) => builder,
+                        Err(e) => {
+                            println!("Failed to create command buffer: {:?}", e);
 
-                    builder
-                        .begin_render_pass(
-                            framebuffers[image_num].clone(),
-                            SubpassContents::Inline,
-                            vec![terminal.config.bg_color.into(), 1_f32.into()],
-                        )
-                        .unwrap();
+                            recreate_swapchain = true;
+                            frame_failures.failed(control_flow);
 
-                    Self::draw_terminal(
-                        &mut builder,
-                        pipeline.clone(),
-                        &uniform_buffer,
-                        &frag_uniform_buffer,
-                        &performer.read().unwrap(),
-                        &cursor,
-                        proj,
-                        &terminal,
-                    );
+                            return;
+                        }
+                    };
+
+                    if let Err(e) = builder.begin_render_pass(
+                        framebuffers[image_num].clone(),
+                        SubpassContents::Inline,
+                        vec![
+                            Self::clear_color(clear_color.into(), swapchain.composite_alpha())
+                                .into(),
+                        ],
+                    ) {
+                        println!("Failed to begin render pass: {:?}", e);
+
+                        recreate_swapchain = true;
+                        frame_failures.failed(control_flow);
 
-                    builder.end_render_pass().unwrap();
+                        return;
+                    }
+
+                    if confirming_close {
+                        Self::draw_text(
+                            &mut builder,
+                            pipeline.clone(),
+                            &terminal,
+                            &uniform_buffer,
+                            &frag_uniform_buffer,
+                            &quad,
+                            proj,
+                            Vector2::new(-1.0, 0.0),
+                            queue.clone(),
+                            &performer.read().unwrap().font,
+                            CLOSE_PROMPT,
+                        );
+                    } else {
+                        let performer = performer.read().unwrap();
+                        // A program's DECSET 12 overrides the configured blink.
+                        let blink = performer
+                            .modes
+                            .cursor_blink
+                            .unwrap_or(terminal.config.cursor_blink);
+
+                        Self::draw_terminal(
+                            &mut builder,
+                            queue.clone(),
+                            pipeline.clone(),
+                            &uniform_buffer,
+                            &frag_uniform_buffer,
+                            &quad,
+                            &performer,
+                            &quads,
+                            proj,
+                            (!blink || Self::blink_on(terminal.config.cursor_blink_ms, started))
+                                .then_some(cursor_pos),
+                            Self::blink_on(terminal.config.text_blink_ms, started),
+                            &terminal,
+                        );
+                    }
 
-                    let command_buffer = builder.build().unwrap();
-                    let future = previous_frame_end
+                    if dim > 0.0 {
+                        Self::draw_item(
+                            &mut builder,
+                            pipeline.clone(),
+                            &uniform_buffer,
+                            &frag_uniform_buffer,
+                            &quad,
+                            proj,
+                            Vector2::new(-1.0, -1.0),
+                            Vector4::new(0.0, 0.0, 0.0, dim * (1.0 - DIM_BRIGHTNESS)),
+                            terminal.config.font.gamma,
+                            &quads.screen,
+                        );
+                    }
+
+                    if let Err(e) = builder.end_render_pass() {
+                        println!("Failed to end render pass: {:?}", e);
+
+                        recreate_swapchain = true;
+                        frame_failures.failed(control_flow);
+
+                        return;
+                    }
+
+                    let command_buffer = match builder.build() {
+                        Ok(command_buffer) => command_buffer,
+                        Err(e) => {
+                            println!("Failed to build command buffer: {:?}", e);
+
+                            recreate_swapchain = true;
+                            frame_failures.failed(control_flow);
+
+                            return;
+                        }
+                    };
+                    let future = match previous_frame_end
                         .take()
                         .unwrap()
                         .join(acquire_future)
                         .then_execute(queue.clone(), command_buffer)
-                        .unwrap()
-                        .then_swapchain_present(queue.clone(), swapchain.clone(), image_num)
-                        .then_signal_fence_and_flush();
+                    {
+                        Ok(future) => future,
+                        Err(e) => {
+                            println!("Failed to execute command buffer: {:?}", e);
+
+                            recreate_swapchain = true;
+                            previous_frame_end = Some(sync::now(device.clone()).boxed());
+                            frame_failures.failed(control_flow);
+
+                            return;
+                        }
+                    }
+                    .then_swapchain_present(queue.clone(), swapchain.clone(), image_num)
+                    .then_signal_fence_and_flush();
 
                     match future {
                         Ok(future) => {
+                            frame_failures = FrameFailures::default();
                             previous_frame_end = Some(future.boxed());
                         }
                         Err(FlushError::OutOfDate) => {
@@ -295,7 +746,9 @@ impl Renderer {
                         Err(e) => {
                             println!("Failed to flush future: {:?}", e);
 
+                            recreate_swapchain = true;
                             previous_frame_end = Some(sync::now(device.clone()).boxed());
+                            frame_failures.failed(control_flow);
                         }
                     }
                 }
@@ -304,49 +757,832 @@ impl Renderer {
         });
     }
 
+    fn reload_config(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        terminal: &mut Terminal,
+        performer: &RwLock<Performer>,
+        quads: &mut Quads,
+    ) -> anyhow::Result<()> {
+        let config = Config::default_from_file()?;
+        let font_changed = config.font.path != terminal.config.font.path
+            || config.font.scale != terminal.config.font.scale
+            || config.font.antialias != terminal.config.font.antialias
+            || config.font.powerline_stretch != terminal.config.font.powerline_stretch
+            || config.font.subpixel != terminal.config.font.subpixel
+            || config.font.subpixel_order != terminal.config.font.subpixel_order
+            || config.fonts != terminal.config.fonts;
+        let margin_changed = config.bottom_margin_rows != terminal.config.bottom_margin_rows;
+        let mut performer = performer.write().unwrap();
+
+        if font_changed {
+            let scale_factor = performer.font.scale_factor;
+
+            Self::load_font(device, queue, &config, &mut performer, quads, scale_factor)?;
+        }
+
+        if margin_changed {
+            performer.set_bottom_margin(config.bottom_margin_rows as usize);
+        }
+
+        performer.clear_keeps_prompt = config.clear_keeps_prompt;
+        performer.invalid_utf8 = config.invalid_utf8;
+
+        if font_changed || margin_changed {
+            terminal.pty.resize(&performer.winsize())?;
+        }
+
+        terminal.config = config;
+
+        Ok(())
+    }
+
+    fn set_font_scale(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        terminal: &mut Terminal,
+        performer: &RwLock<Performer>,
+        quads: &mut Quads,
+        scale: f32,
+    ) -> anyhow::Result<()> {
+        if scale == terminal.config.font.scale {
+            return Ok(());
+        }
+
+        let mut performer = performer.write().unwrap();
+
+        terminal.config.font.scale = scale;
+
+        let scale_factor = performer.font.scale_factor;
+
+        Self::load_font(
+            device,
+            queue,
+            &terminal.config,
+            &mut performer,
+            quads,
+            scale_factor,
+        )?;
+
+        // The cell size changed, so the shell is told about the new grid.
+        terminal.pty.resize(&performer.winsize())?;
+
+        Ok(())
+    }
+
+    fn load_font(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        config: &Config,
+        performer: &mut Performer,
+        quads: &mut Quads,
+        scale_factor: f32,
+    ) -> anyhow::Result<()> {
+        let font = Arc::new(LoadedFont::from_file(config, scale_factor)?);
+
+        *quads = Quads::from_font(device, queue, font.scale, font.cell_width())?;
+
+        performer.set_font(font);
+
+        Ok(())
+    }
+
+    // Validation is a debugging aid, so a missing layer only disables it instead of failing.
+    fn instance(validation: bool) -> anyhow::Result<(Arc<Instance>, Option<DebugCallback>)> {
+        let validation = validation
+            && match layers_list() {
+                Ok(mut layers) => {
+                    let found = layers.any(|layer| layer.name() == VALIDATION_LAYER);
+
+                    if !found {
+                        println!("Error: {} is not installed", VALIDATION_LAYER);
+                    }
+
+                    found
+                }
+                Err(e) => {
+                    println!("{}", e);
+
+                    false
+                }
+            };
+        let instance = Instance::new(InstanceCreateInfo {
+            enabled_extensions: InstanceExtensions {
+                ext_debug_utils: validation,
+                ..vulkano_win::required_extensions()
+            },
+            enabled_layers: if validation {
+                vec![VALIDATION_LAYER.to_owned()]
+            } else {
+                Vec::new()
+            },
+            ..Default::default()
+        })?;
+
+        if !validation {
+            return Ok((instance, None));
+        }
+
+        let debug_callback = DebugCallback::new(
+            &instance,
+            MessageSeverity::errors_and_warnings(),
+            MessageType::all(),
+            |message| {
+                eprintln!(
+                    "{}: {}",
+                    message.layer_prefix.unwrap_or("vulkan"),
+                    message.description
+                )
+            },
+        )?;
+
+        Ok((instance, Some(debug_callback)))
+    }
+
+    pub fn list_gpus() -> anyhow::Result<()> {
+        let instance = Instance::new(InstanceCreateInfo {
+            enabled_extensions: vulkano_win::required_extensions(),
+            ..Default::default()
+        })?;
+
+        for (i, device) in PhysicalDevice::enumerate(&instance).enumerate() {
+            let properties = device.properties();
+
+            println!(
+                "{}: {} ({:?})",
+                i, properties.device_name, properties.device_type
+            );
+        }
+
+        Ok(())
+    }
+
+    fn mouse_modifiers(input: &WinitInputHelper) -> u8 {
+        mouse::modifiers(input.held_shift(), input.held_alt(), input.held_control())
+    }
+
+    fn report_mouse(sender: &Sender<Vec<u8>>, report: Option<Vec<u8>>) {
+        if let Some(report) = report {
+            if let Err(e) = sender.send(report) {
+                println!("Error on mouse report: {:?}", e);
+            }
+        }
+    }
+
+    // Auto uses Mailbox while output is streaming in and goes back to vsync once it settles.
+    fn present_mode(
+        present_mode: PresentMode,
+        supported: &[swapchain::PresentMode],
+        active: bool,
+    ) -> swapchain::PresentMode {
+        let mailbox = match present_mode {
+            PresentMode::Fifo => false,
+            PresentMode::Mailbox => true,
+            PresentMode::Auto => active,
+        };
+
+        if mailbox && supported.contains(&swapchain::PresentMode::Mailbox) {
+            swapchain::PresentMode::Mailbox
+        } else {
+            swapchain::PresentMode::Fifo
+        }
+    }
+
+    // The font scale a zoom binding asks for, if one was pressed.
+    fn zoom(config: &Config, input: &WinitInputHelper, configured_scale: f32) -> Option<f32> {
+        let pressed = |bindings: &[KeyBinding]| bindings.iter().any(|b| b.pressed(input));
+
+        if config.reset_font_size_binding.pressed(input) {
+            Some(configured_scale)
+        } else if pressed(&config.zoom_in_bindings) {
+            Some(config.font.scale * ZOOM_STEP)
+        } else if pressed(&config.zoom_out_bindings) {
+            Some((config.font.scale / ZOOM_STEP).max(MIN_FONT_SCALE))
+        } else {
+            None
+        }
+    }
+
+    // How far into the fade after dim_after_secs without input or output, 0 is full brightness.
+    fn dim(dim_after_secs: u64, idle: Duration) -> f32 {
+        match dim_after_secs {
+            0 => 0.0,
+            secs => (idle.saturating_sub(Duration::from_secs(secs)).as_secs_f32()
+                / DIM_FADE.as_secs_f32())
+            .min(1.0),
+        }
+    }
+
+    fn control_flow(focused: bool, unfocused_fps: u32) -> ControlFlow {
+        match (focused, unfocused_fps) {
+            (true, _) => ControlFlow::Poll,
+            (false, 0) => ControlFlow::Wait,
+            (false, fps) => ControlFlow::WaitUntil(Instant::now() + Duration::from_secs(1) / fps),
+        }
+    }
+
+    // An interval of 0 never blinks.
+    fn blink_on(interval: u64, started: Instant) -> bool {
+        interval == 0 || (started.elapsed().as_millis() / interval as u128).is_multiple_of(2)
+    }
+
+    fn animate_cursor(current: Vector2<f32>, target: Vector2<f32>, animate: bool) -> Vector2<f32> {
+        // Long jumps snap so the cursor never trails far behind where the text is going.
+        if !animate || (target - current).magnitude() > CURSOR_SNAP_DISTANCE {
+            target
+        } else {
+            current.lerp(target, CURSOR_EASING)
+        }
+    }
+
+    fn composite_alpha(supported: &SupportedCompositeAlpha, transparent: bool) -> CompositeAlpha {
+        if transparent && supported.post_multiplied {
+            CompositeAlpha::PostMultiplied
+        } else if transparent && supported.pre_multiplied {
+            CompositeAlpha::PreMultiplied
+        } else {
+            supported.iter().next().unwrap()
+        }
+    }
+
+    // DECSCNM swaps the default colors for the whole screen, the background keeps its opacity.
+    fn default_colors(config: &Config, performer: &Performer) -> (Vector4<f32>, Vector4<f32>) {
+        let fg = Vector4::from(config.font.color);
+        let bg = Vector4::from(config.bg_color);
+
+        if performer.modes.reverse_screen {
+            (bg.truncate().extend(fg.w), fg.truncate().extend(bg.w))
+        } else {
+            (fg, bg)
+        }
+    }
+
+    fn clear_color(bg_color: [f32; 4], composite_alpha: CompositeAlpha) -> [f32; 4] {
+        match composite_alpha {
+            CompositeAlpha::PreMultiplied => {
+                let [r, g, b, a] = bg_color;
+
+                [r * a, g * a, b * a, a]
+            }
+            _ => bg_color,
+        }
+    }
+
+    fn window_icon(config: &Config) -> Option<Icon> {
+        let icon = match &config.icon {
+            Some(path) => icon::load(path),
+            None => icon::default_icon(),
+        };
+
+        match icon {
+            Ok(icon) => Some(icon),
+            Err(e) => {
+                println!("Error loading icon: {:?}", e);
+
+                None
+            }
+        }
+    }
+
+    fn window_builder(terminal: &Terminal) -> WindowBuilder {
+        let window_builder = WindowBuilder::new()
+            .with_title(APP_NAME)
+            .with_transparent(terminal.config.bg_color[3] < 1.0)
+            .with_window_icon(Self::window_icon(&terminal.config))
+            .with_decorations(!terminal.config.dropdown)
+            .with_always_on_top(terminal.config.dropdown);
+
+        #[cfg(all(unix, not(target_os = "macos")))]
+        let window_builder = match &terminal.config.app_id {
+            Some(app_id) => window_builder
+                .with_app_id(app_id.clone())
+                .with_class(app_id.clone(), app_id.clone()),
+            None => window_builder,
+        };
+
+        window_builder
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn draw_terminal(
         builder: &mut AutoCommandBufferBuilder<
             PrimaryAutoCommandBuffer,
             StandardCommandPoolBuilder,
         >,
+        queue: Arc<Queue>,
         pipeline: Arc<GraphicsPipeline>,
         uniform_buffer: &CpuBufferPool<vertex::ty::Data>,
         frag_uniform_buffer: &CpuBufferPool<fragment::ty::Data>,
+        quad: &Mesh,
         performer: &Performer,
-        cursor: &Item,
+        quads: &Quads,
         proj: Matrix4<f32>,
+        cursor_pos: Option<Vector2<f32>>,
+        blink_on: bool,
         terminal: &Terminal,
     ) {
-        for drawable in &*terminal.screen.read().unwrap() {
-            if let RenderItem::Chr(chr) = &drawable.render_item {
+        let screen = terminal.screen.read().unwrap();
+        // Drawables that have left the grid are culled once up front instead of in every layer.
+        // Combining marks sit slightly off their cell, so the bounds have half a cell of slack.
+        let (half_width, half_height) = (
+            performer.font.cell_width() / 2.0,
+            performer.font.scale / 2.0,
+        );
+        let right = 1.0 - half_width;
+        let bottom = -1.0 + performer.font.scale * performer.rows as f32 - half_height;
+        let screen = screen
+            .iter()
+            .filter(|d| {
+                (-1.0 - half_width..right).contains(&d.pos.x)
+                    && (-1.0 - half_height..bottom).contains(&d.pos.y)
+            })
+            .collect::<Vec<_>>();
+        let (fg, bg) = Self::default_colors(&terminal.config, performer);
+        let bold_is_bright = terminal.config.bold_is_bright;
+        let skew = terminal.config.fake_italic_skew;
+        let subpixel = terminal.config.font.subpixel as i32;
+        let darkening = if terminal.config.font.stem_darkening {
+            STEM_DARKENING
+        } else {
+            0.0
+        };
+
+        // Layers, bottom to top: padding, cell backgrounds, the debug_cells checkerboard, glyphs and
+        // images, overlines, underlines, then the cursor.
+        if let Some(padding_color) = terminal.config.padding_color {
+            Self::draw_padding(
+                builder,
+                pipeline.clone(),
+                uniform_buffer,
+                frag_uniform_buffer,
+                quad,
+                performer,
+                quads,
+                proj,
+                padding_color.into(),
+                terminal.config.font.gamma,
+            );
+        }
+
+        for drawable in screen.iter().filter(|d| d.style.bg != Color::Default) {
+            Self::draw_item(
+                builder,
+                pipeline.clone(),
+                uniform_buffer,
+                frag_uniform_buffer,
+                quad,
+                proj,
+                drawable.pos,
+                drawable.style.background(bg),
+                terminal.config.font.gamma,
+                &quads.cell,
+            );
+        }
+
+        if terminal.config.debug_cells {
+            let tint = Vector4::new(fg.x, fg.y, fg.z, DEBUG_CELL_ALPHA);
+
+            for (row, col) in (0..performer.rows)
+                .flat_map(|row| (0..performer.cols).map(move |col| (row, col)))
+                .filter(|(row, col)| (row + col) % 2 == 0)
+            {
                 Self::draw_item(
                     builder,
                     pipeline.clone(),
-                    terminal,
                     uniform_buffer,
                     frag_uniform_buffer,
+                    quad,
                     proj,
-                    drawable.pos,
-                    &chr.item,
+                    Vector2::new(
+                        -1.0 + performer.font.cell_width() * col as f32,
+                        -1.0 + performer.font.scale * row as f32,
+                    ),
+                    tint,
+                    terminal.config.font.gamma,
+                    &quads.cell,
                 );
             }
         }
 
+        for drawable in screen.iter() {
+            match &drawable.render_item {
+                // Blinking text is hidden during the off phase, its background and lines stay.
+                RenderItem::Chr(_) if drawable.style.blink && !blink_on => {}
+                RenderItem::Chr(chr) => match chr.item(queue.clone()).and_then(|item| {
+                    Self::draw(
+                        builder,
+                        pipeline.clone(),
+                        uniform_buffer,
+                        frag_uniform_buffer,
+                        quad,
+                        proj,
+                        drawable.pos + chr.offset(),
+                        Vector2::new(drawable.style.skew(skew), drawable.pos.y),
+                        fragment::ty::Data {
+                            color: enforce_contrast(
+                                drawable.style.foreground(fg, bg, bold_is_bright),
+                                drawable.style.background(bg),
+                                terminal.config.minimum_contrast,
+                            )
+                            .into(),
+                            background: drawable.style.background(bg).into(),
+                            gamma: terminal.config.font.gamma,
+                            image: 0,
+                            darkening,
+                            subpixel,
+                        },
+                        item,
+                    )
+                }) {
+                    Ok(()) => {}
+                    Err(e) => println!("Error drawing glyph: {}", e),
+                },
+                RenderItem::Image(image) => match image.item(queue.clone()).and_then(|item| {
+                    Self::draw(
+                        builder,
+                        pipeline.clone(),
+                        uniform_buffer,
+                        frag_uniform_buffer,
+                        quad,
+                        proj,
+                        drawable.pos,
+                        Vector2::new(0.0, 0.0),
+                        fragment::ty::Data {
+                            color: [1.0; 4],
+                            background: [0.0; 4],
+                            gamma: 1.0,
+                            image: 1,
+                            darkening: 0.0,
+                            subpixel: 0,
+                        },
+                        item,
+                    )
+                }) {
+                    Ok(()) => {}
+                    Err(e) => println!("Error drawing image: {}", e),
+                },
+                RenderItem::Space | RenderItem::Tab(_) => {}
+            }
+        }
+
+        if terminal.config.show_tabs || terminal.config.show_trailing_whitespace {
+            // Whitespace is marked with the first of its markers the font has, at half strength.
+            let row = |pos: Vector2<f32>| (pos.y / performer.font.scale).round() as i64;
+            let mut line_ends = HashMap::new();
+
+            for drawable in screen
+                .iter()
+                .filter(|d| matches!(d.render_item, RenderItem::Chr(_)))
+            {
+                let end = line_ends.entry(row(drawable.pos)).or_insert(drawable.pos.x);
+
+                *end = end.max(drawable.pos.x);
+            }
+
+            for drawable in screen.iter() {
+                let markers = match drawable.render_item {
+                    RenderItem::Tab(_) if terminal.config.show_tabs => TAB_MARKERS,
+                    RenderItem::Space
+                        if terminal.config.show_trailing_whitespace
+                            && line_ends
+                                .get(&row(drawable.pos))
+                                .is_none_or(|end| drawable.pos.x > *end) =>
+                    {
+                        SPACE_MARKERS
+                    }
+                    _ => continue,
+                };
+                let chr = match markers.into_iter().find_map(|c| performer.font.get_chr(c)) {
+                    Some(chr) => chr,
+                    None => continue,
+                };
+                let cell_bg = drawable.style.background(bg);
+
+                match chr.item(queue.clone()).and_then(|item| {
+                    Self::draw(
+                        builder,
+                        pipeline.clone(),
+                        uniform_buffer,
+                        frag_uniform_buffer,
+                        quad,
+                        proj,
+                        drawable.pos + chr.offset(),
+                        Vector2::new(0.0, 0.0),
+                        fragment::ty::Data {
+                            color: drawable
+                                .style
+                                .foreground(fg, bg, bold_is_bright)
+                                .lerp(cell_bg, 0.5)
+                                .into(),
+                            background: cell_bg.into(),
+                            gamma: terminal.config.font.gamma,
+                            image: 0,
+                            darkening,
+                            subpixel,
+                        },
+                        item,
+                    )
+                }) {
+                    Ok(()) => {}
+                    Err(e) => println!("Error drawing glyph: {}", e),
+                }
+            }
+        }
+
+        for drawable in screen.iter().filter(|d| d.style.overline) {
+            Self::draw_item(
+                builder,
+                pipeline.clone(),
+                uniform_buffer,
+                frag_uniform_buffer,
+                quad,
+                proj,
+                drawable.pos,
+                drawable.style.foreground(fg, bg, bold_is_bright),
+                terminal.config.font.gamma,
+                &quads.line,
+            );
+        }
+
+        for drawable in screen
+            .iter()
+            .filter(|d| d.style.underline != Underline::None)
+        {
+            Self::draw_underline(
+                builder,
+                pipeline.clone(),
+                uniform_buffer,
+                frag_uniform_buffer,
+                quad,
+                proj,
+                drawable.pos,
+                drawable.style.underline_color(fg, bg, bold_is_bright),
+                terminal.config.font.gamma,
+                performer.font.scale,
+                performer.font.cell_width(),
+                drawable.style.underline,
+                quads,
+            );
+        }
+
+        let cursor_pos = match cursor_pos {
+            Some(cursor_pos) => cursor_pos,
+            None => return,
+        };
+
+        // A block cursor inverts the cell it covers: it is filled with the cell's foreground and the
+        // glyph is drawn again on top in the cell's background, so it stays visible on any colors.
+        // When the two are too close to tell apart, black or white is used instead.
+        let covered = screen.iter().rev().find(|d| {
+            in_cell(
+                d.pos,
+                performer.pos,
+                performer.font.cell_width(),
+                performer.font.scale,
+            )
+        });
+        let (cell_fg, cell_bg) = match covered {
+            Some(drawable) => (
+                drawable.style.foreground(fg, bg, bold_is_bright),
+                drawable.style.background(bg),
+            ),
+            None => (fg, bg),
+        };
+        let (cursor_fg, cursor_bg) = match terminal.config.cursor_color {
+            CursorColor::Auto
+                if (luminance(cell_fg) - luminance(cell_bg)).abs() >= MIN_CONTRAST =>
+            {
+                (cell_fg, cell_bg)
+            }
+            CursorColor::Auto => (contrasting(cell_bg), cell_bg),
+            CursorColor::Fixed(color) => (color.into(), contrasting(color.into())),
+        };
+        let covered_chr = match covered.map(|d| &d.render_item) {
+            Some(RenderItem::Chr(chr)) => Some(chr),
+            _ => None,
+        };
+        let outline = match terminal.config.cursor_style {
+            CursorStyle::Block => false,
+            CursorStyle::BlockOverEmpty => covered_chr.is_some(),
+            CursorStyle::Outline => true,
+        };
+
+        if outline {
+            Self::draw_outline(
+                builder,
+                pipeline,
+                uniform_buffer,
+                frag_uniform_buffer,
+                quad,
+                proj,
+                cursor_pos,
+                cursor_fg,
+                terminal.config.font.gamma,
+                performer.font.scale,
+                performer.font.cell_width(),
+                quads,
+            );
+
+            return;
+        }
+
         Self::draw_item(
             builder,
-            pipeline,
-            terminal,
+            pipeline.clone(),
             uniform_buffer,
             frag_uniform_buffer,
+            quad,
             proj,
-            performer.pos,
-            cursor,
+            cursor_pos,
+            cursor_fg,
+            terminal.config.font.gamma,
+            &quads.cell,
         );
+
+        if let Some(chr) = covered_chr {
+            match chr.item(queue.clone()).and_then(|item| {
+                Self::draw(
+                    builder,
+                    pipeline,
+                    uniform_buffer,
+                    frag_uniform_buffer,
+                    quad,
+                    proj,
+                    cursor_pos + chr.offset(),
+                    Vector2::new(covered.map_or(0.0, |d| d.style.skew(skew)), cursor_pos.y),
+                    fragment::ty::Data {
+                        color: [cursor_bg.x, cursor_bg.y, cursor_bg.z, 1.0],
+                        background: cursor_fg.into(),
+                        gamma: terminal.config.font.gamma,
+                        image: 0,
+                        darkening: 0.0,
+                        subpixel,
+                    },
+                    item,
+                )
+            }) {
+                Ok(()) => {}
+                Err(e) => println!("Error drawing glyph: {}", e),
+            }
+        }
     }
 
+    // Padding is everything outside the grid: the rows below it and the sliver to its right.
     #[allow(clippy::too_many_arguments)]
-    fn draw_item(
+    fn draw_padding(
+        builder: &mut AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer,
+            StandardCommandPoolBuilder,
+        >,
+        pipeline: Arc<GraphicsPipeline>,
+        uniform_buffer: &CpuBufferPool<vertex::ty::Data>,
+        frag_uniform_buffer: &CpuBufferPool<fragment::ty::Data>,
+        quad: &Mesh,
+        performer: &Performer,
+        quads: &Quads,
+        proj: Matrix4<f32>,
+        color: Vector4<f32>,
+        gamma: f32,
+    ) {
+        let (rows, _) = grid_size(performer.font.scale, performer.font.cell_width(), 0);
+
+        for row in 0..=rows {
+            for col in
+                (0..=performer.cols).filter(|col| row >= performer.rows || *col == performer.cols)
+            {
+                Self::draw_item(
+                    builder,
+                    pipeline.clone(),
+                    uniform_buffer,
+                    frag_uniform_buffer,
+                    quad,
+                    proj,
+                    Vector2::new(
+                        -1.0 + performer.font.cell_width() * col as f32,
+                        -1.0 + performer.font.scale * row as f32,
+                    ),
+                    color,
+                    gamma,
+                    &quads.cell,
+                );
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_outline(
+        builder: &mut AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer,
+            StandardCommandPoolBuilder,
+        >,
+        pipeline: Arc<GraphicsPipeline>,
+        uniform_buffer: &CpuBufferPool<vertex::ty::Data>,
+        frag_uniform_buffer: &CpuBufferPool<fragment::ty::Data>,
+        quad: &Mesh,
+        proj: Matrix4<f32>,
+        pos: Vector2<f32>,
+        color: Vector4<f32>,
+        gamma: f32,
+        scale: f32,
+        cell_width: f32,
+        quads: &Quads,
+    ) {
+        let thickness = scale / LINE_THICKNESS;
+        let edges = [
+            (Vector2::new(0.0, 0.0), &quads.line),
+            (Vector2::new(0.0, scale - thickness), &quads.line),
+            (Vector2::new(0.0, 0.0), &quads.column),
+            (Vector2::new(cell_width - thickness, 0.0), &quads.column),
+        ];
+
+        for (offset, item) in edges {
+            Self::draw_item(
+                builder,
+                pipeline.clone(),
+                uniform_buffer,
+                frag_uniform_buffer,
+                quad,
+                proj,
+                pos + offset,
+                color,
+                gamma,
+                item,
+            );
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_underline(
+        builder: &mut AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer,
+            StandardCommandPoolBuilder,
+        >,
+        pipeline: Arc<GraphicsPipeline>,
+        uniform_buffer: &CpuBufferPool<vertex::ty::Data>,
+        frag_uniform_buffer: &CpuBufferPool<fragment::ty::Data>,
+        quad: &Mesh,
+        proj: Matrix4<f32>,
+        pos: Vector2<f32>,
+        color: Vector4<f32>,
+        gamma: f32,
+        scale: f32,
+        cell_width: f32,
+        underline: Underline,
+        quads: &Quads,
+    ) {
+        let thickness = scale / LINE_THICKNESS;
+        let bottom = pos + Vector2::new(0.0, scale - thickness);
+        let dash_width = cell_width / DASH_SEGMENTS as f32;
+        let (lines, dashes): (&[f32], &[(usize, f32)]) = match underline {
+            Underline::None => (&[], &[]),
+            Underline::Single => (&[0.0], &[]),
+            Underline::Double => (&[0.0, -2.0 * thickness], &[]),
+            Underline::Curly => (
+                &[],
+                &[
+                    (0, 0.0),
+                    (1, -thickness),
+                    (2, -2.0 * thickness),
+                    (3, -thickness),
+                ],
+            ),
+            Underline::Dotted => (&[], &[(0, 0.0), (2, 0.0)]),
+            Underline::Dashed => (&[], &[(0, 0.0), (1, 0.0)]),
+        };
+
+        for offset in lines {
+            Self::draw_item(
+                builder,
+                pipeline.clone(),
+                uniform_buffer,
+                frag_uniform_buffer,
+                quad,
+                proj,
+                bottom + Vector2::new(0.0, *offset),
+                color,
+                gamma,
+                &quads.line,
+            );
+        }
+
+        for (segment, offset) in dashes {
+            Self::draw_item(
+                builder,
+                pipeline.clone(),
+                uniform_buffer,
+                frag_uniform_buffer,
+                quad,
+                proj,
+                bottom + Vector2::new(dash_width * *segment as f32, *offset),
+                color,
+                gamma,
+                &quads.dash,
+            );
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_text(
         builder: &mut AutoCommandBufferBuilder<
             PrimaryAutoCommandBuffer,
             StandardCommandPoolBuilder,
@@ -355,27 +1591,119 @@ impl Renderer {
         terminal: &Terminal,
         uniform_buffer: &CpuBufferPool<vertex::ty::Data>,
         frag_uniform_buffer: &CpuBufferPool<fragment::ty::Data>,
+        quad: &Mesh,
+        proj: Matrix4<f32>,
+        mut pos: Vector2<f32>,
+        queue: Arc<Queue>,
+        font: &LoadedFont,
+        text: &str,
+    ) {
+        for c in text.chars() {
+            match font.get_chr(c) {
+                Some(chr) => {
+                    match chr.item(queue.clone()).and_then(|item| {
+                        Self::draw(
+                            builder,
+                            pipeline.clone(),
+                            uniform_buffer,
+                            frag_uniform_buffer,
+                            quad,
+                            proj,
+                            pos + chr.offset(),
+                            Vector2::new(0.0, 0.0),
+                            fragment::ty::Data {
+                                color: terminal.config.font.color,
+                                background: terminal.config.bg_color,
+                                gamma: terminal.config.font.gamma,
+                                image: 0,
+                                darkening: 0.0,
+                                subpixel: terminal.config.font.subpixel as i32,
+                            },
+                            item,
+                        )
+                    }) {
+                        Ok(()) => {}
+                        Err(e) => println!("Error drawing glyph: {}", e),
+                    }
+
+                    pos.x += font.cell_width();
+                }
+                None => pos.x += font.cell_width(),
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_item(
+        builder: &mut AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer,
+            StandardCommandPoolBuilder,
+        >,
+        pipeline: Arc<GraphicsPipeline>,
+        uniform_buffer: &CpuBufferPool<vertex::ty::Data>,
+        frag_uniform_buffer: &CpuBufferPool<fragment::ty::Data>,
+        quad: &Mesh,
         proj: Matrix4<f32>,
         pos: Vector2<f32>,
+        color: Vector4<f32>,
+        gamma: f32,
         item: &Item,
     ) {
+        if let Err(e) = Self::draw(
+            builder,
+            pipeline,
+            uniform_buffer,
+            frag_uniform_buffer,
+            quad,
+            proj,
+            pos,
+            Vector2::new(0.0, 0.0),
+            fragment::ty::Data {
+                color: color.into(),
+                background: [0.0; 4],
+                gamma,
+                image: 0,
+                darkening: 0.0,
+                subpixel: 0,
+            },
+            item,
+        ) {
+            println!("Error drawing: {}", e);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        builder: &mut AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer,
+            StandardCommandPoolBuilder,
+        >,
+        pipeline: Arc<GraphicsPipeline>,
+        uniform_buffer: &CpuBufferPool<vertex::ty::Data>,
+        frag_uniform_buffer: &CpuBufferPool<fragment::ty::Data>,
+        quad: &Mesh,
+        proj: Matrix4<f32>,
+        pos: Vector2<f32>,
+        shear: Vector2<f32>,
+        frag_data: fragment::ty::Data,
+        item: &Item,
+    ) -> anyhow::Result<()> {
         let uniform_buffer_subbuffer = {
             let uniform_data = vertex::ty::Data {
                 proj: proj.into(),
-                transform: Matrix4::from_translation(pos.extend(0.0)).into(),
+                transform: (Matrix4::from_translation(pos.extend(0.0))
+                    * Matrix4::from_nonuniform_scale(item.dimensions.x, item.dimensions.y, 1.0))
+                .into(),
+                shear: shear.into(),
             };
 
-            Arc::new(uniform_buffer.next(uniform_data).unwrap())
-        };
-        let frag_uniform_buffer_subbuffer = {
-            let uniform_data = fragment::ty::Data {
-                color: terminal.config.font_color,
-            };
-
-            Arc::new(frag_uniform_buffer.next(uniform_data).unwrap())
+            Arc::new(uniform_buffer.next(uniform_data)?)
         };
+        let frag_uniform_buffer_subbuffer = Arc::new(frag_uniform_buffer.next(frag_data)?);
         let descriptor_set_layouts = pipeline.layout().set_layouts();
-        let set_layout = descriptor_set_layouts.get(0).unwrap();
+        let set_layout = descriptor_set_layouts
+            .first()
+            .ok_or(RendererError::NoDescriptorSetLayout)?;
         let set = PersistentDescriptorSet::new(
             set_layout.clone(),
             [
@@ -387,8 +1715,7 @@ impl Renderer {
                     item.texture.sampler.clone(),
                 ),
             ],
-        )
-        .unwrap();
+        )?;
 
         builder
             .bind_pipeline_graphics(pipeline.clone())
@@ -398,10 +1725,11 @@ impl Renderer {
                 0,
                 set,
             )
-            .bind_vertex_buffers(0, item.mesh.vertices.clone())
-            .bind_index_buffer(item.mesh.indices.clone())
-            .draw_indexed(item.mesh.indices.len() as u32, 1, 0, 0, 0)
-            .unwrap();
+            .bind_vertex_buffers(0, quad.vertices.clone())
+            .bind_index_buffer(quad.indices.clone())
+            .draw_indexed(quad.indices.len() as u32, 1, 0, 0, 0)?;
+
+        Ok(())
     }
 
     fn window_size_dependent_setup(
@@ -411,26 +1739,20 @@ impl Renderer {
         images: &[Arc<SwapchainImage<Window>>],
     ) -> anyhow::Result<(Arc<GraphicsPipeline>, Vec<Arc<Framebuffer>>)> {
         let dimensions = images[0].dimensions().width_height();
-        let depth = ImageView::new_default(AttachmentImage::transient(
-            device.clone(),
-            dimensions,
-            Format::D16_UNORM,
-        )?)?;
         let framebuffers = images
             .iter()
             .map(|image| {
-                let view = ImageView::new_default(image.clone()).unwrap();
+                let view = ImageView::new_default(image.clone())?;
 
-                Framebuffer::new(
+                Ok(Framebuffer::new(
                     render_pass.clone(),
                     FramebufferCreateInfo {
-                        attachments: vec![view, depth.clone()],
+                        attachments: vec![view],
                         ..Default::default()
                     },
-                )
-                .unwrap()
+                )?)
             })
-            .collect();
+            .collect::<anyhow::Result<_>>()?;
         let subpass = Subpass::from(render_pass, 0).unwrap();
         let pipeline = GraphicsPipeline::start()
             .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
@@ -446,11 +1768,187 @@ impl Renderer {
                 },
             ]))
             .fragment_shader(shaders.fragment.entry_point("main").unwrap(), ())
-            .depth_stencil_state(DepthStencilState::simple_depth_test())
-            .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend_alpha())
+            .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend(
+                AttachmentBlend {
+                    alpha_source: BlendFactor::One,
+                    ..AttachmentBlend::alpha()
+                },
+            ))
             .render_pass(subpass)
             .build(device)?;
 
         Ok((pipeline, framebuffers))
     }
 }
+
+// Positions are built up from float steps, so two in the same cell can differ by a little rounding.
+fn in_cell(pos: Vector2<f32>, cell: Vector2<f32>, cell_width: f32, scale: f32) -> bool {
+    (pos.x - cell.x).abs() < cell_width / 2.0 && (pos.y - cell.y).abs() < scale / 2.0
+}
+
+// Relative luminance, the channels are sRGB so they are linearized before being weighted.
+fn luminance(color: Vector4<f32>) -> f32 {
+    let linear = color.truncate().map(|c| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+
+    linear.dot(Vector3::new(0.2126, 0.7152, 0.0722))
+}
+
+fn contrasting(color: Vector4<f32>) -> Vector4<f32> {
+    let (black, white) = (
+        Vector4::new(0.0, 0.0, 0.0, 1.0),
+        Vector4::new(1.0, 1.0, 1.0, 1.0),
+    );
+
+    if contrast_ratio(black, color) > contrast_ratio(white, color) {
+        black
+    } else {
+        white
+    }
+}
+
+// WCAG contrast ratio, from 1 for identical colors up to 21 for black on white.
+fn contrast_ratio(a: Vector4<f32>, b: Vector4<f32>) -> f32 {
+    let (a, b) = (luminance(a), luminance(b));
+
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+// Moves the foreground toward black or white, whichever contrasts more with the background, by
+// the smallest amount that reaches the ratio.
+fn enforce_contrast(fg: Vector4<f32>, bg: Vector4<f32>, minimum: f32) -> Vector4<f32> {
+    if contrast_ratio(fg, bg) >= minimum {
+        return fg;
+    }
+
+    let (black, white) = (
+        Vector4::new(0.0, 0.0, 0.0, fg.w),
+        Vector4::new(1.0, 1.0, 1.0, fg.w),
+    );
+    let target = if contrast_ratio(black, bg) > contrast_ratio(white, bg) {
+        black
+    } else {
+        white
+    };
+
+    if contrast_ratio(target, bg) <= minimum {
+        return target;
+    }
+
+    let (mut low, mut high) = (0.0, 1.0);
+
+    for _ in 0..CONTRAST_STEPS {
+        let mid = (low + high) / 2.0;
+
+        if contrast_ratio(fg.lerp(target, mid), bg) >= minimum {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+
+    fg.lerp(target, high)
+}
+
+// Surfaces come and go around suspend and monitor changes, so a failed frame is retried with a
+// fresh swapchain after a growing delay, and only failing for all of FRAME_FAILURE_BUDGET closes
+// the terminal.
+#[derive(Default)]
+struct FrameFailures {
+    count: u32,
+    since: Option<Instant>,
+    retry_at: Option<Instant>,
+    fatal: bool,
+}
+
+impl FrameFailures {
+    fn failed(&mut self, control_flow: &mut ControlFlow) {
+        let now = Instant::now();
+        let since = *self.since.get_or_insert(now);
+
+        self.count += 1;
+
+        if now.duration_since(since) >= FRAME_FAILURE_BUDGET {
+            println!(
+                "Error: rendering failed {} times over {}s, exiting",
+                self.count,
+                FRAME_FAILURE_BUDGET.as_secs()
+            );
+
+            self.fatal = true;
+            *control_flow = ControlFlow::Exit;
+        } else {
+            let delay = (FRAME_RETRY * 2u32.pow((self.count - 1).min(6))).min(FRAME_RETRY_MAX);
+
+            self.retry_at = Some(now + delay);
+            self.wait(control_flow);
+        }
+    }
+
+    // Other events still wake the loop while a retry is pending, those don't get to draw early.
+    fn waiting(&self, control_flow: &mut ControlFlow) -> bool {
+        let waiting = self
+            .retry_at
+            .is_some_and(|retry_at| Instant::now() < retry_at);
+
+        if waiting {
+            self.wait(control_flow);
+        }
+
+        waiting
+    }
+
+    fn wait(&self, control_flow: &mut ControlFlow) {
+        match self.retry_at {
+            Some(retry_at) if *control_flow != ControlFlow::Exit => {
+                *control_flow = ControlFlow::WaitUntil(retry_at)
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RendererError {
+    #[error("Error: no Vulkan device can present to the window, see --list-gpus")]
+    NoDevice,
+    #[error("Error: there is no Vulkan device {0}, see --list-gpus")]
+    NoSuchDevice(usize),
+    #[error("Error: Vulkan device {0} cannot present to the window, see --list-gpus")]
+    UnsupportedDevice(usize),
+    #[error("Error: the pipeline has no descriptor set layout")]
+    NoDescriptorSetLayout,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_cell_allows_rounding() {
+        let (cell_width, scale) = (0.02, 0.04);
+        let cell = Vector2::new(-1.0 + 0.02 * 15.0, -1.0 + 0.04 * 7.0);
+
+        assert!(in_cell(Vector2::new(-0.7, -0.72), cell, cell_width, scale));
+        assert!(!in_cell(
+            Vector2::new(-0.68, -0.72),
+            cell,
+            cell_width,
+            scale
+        ));
+        assert!(!in_cell(Vector2::new(-0.7, -0.68), cell, cell_width, scale));
+    }
+
+    #[test]
+    fn luminance_is_linear() {
+        let grey = Vector4::new(0.5, 0.5, 0.5, 1.0);
+
+        assert!((luminance(grey) - 0.214).abs() < 0.001);
+        assert!((contrast_ratio(Vector4::new(1.0, 1.0, 1.0, 1.0), grey) - 3.98).abs() < 0.01);
+    }
+}
@@ -7,8 +7,39 @@ layout(location = 0) out vec4 f_color;
 layout(set = 0, binding = 2) uniform sampler2D tex;
 layout(set = 0, binding = 1) uniform Data {
     	vec4 color;
+    	vec4 background;
+    	float gamma;
+    	int image;
+    	float darkening;
+    	int subpixel;
 } uniforms;
 
 void main() {
-    	f_color = texture(tex, tex_coord).r * uniforms.color;
+    	vec4 texel = texture(tex, tex_coord);
+
+    	if (uniforms.image != 0) {
+    		f_color = texel * uniforms.color;
+
+    		return;
+    	}
+
+    	float luminance = dot(uniforms.color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    	// Light glyphs on a dark background look thinner than dark ones on a light background.
+    	float boost = 1.0 + uniforms.darkening * luminance;
+
+    	if (uniforms.subpixel != 0) {
+    		vec3 coverage = min(pow(texel.rgb, vec3(1.0 / uniforms.gamma)) * boost, 1.0);
+    		float alpha = max(max(coverage.r, coverage.g), coverage.b);
+    		// Without dual-source blending the per-channel coverage is folded into one alpha, which
+    		// is exact when the glyph is drawn over the background it is given.
+    		vec3 rgb = alpha > 0.0
+    			? mix(uniforms.background.rgb, uniforms.color.rgb, coverage / alpha)
+    			: uniforms.color.rgb;
+
+    		f_color = vec4(rgb, uniforms.color.a * alpha);
+    	} else {
+    		float coverage = min(pow(texel.r, 1.0 / uniforms.gamma) * boost, 1.0);
+
+    		f_color = vec4(uniforms.color.rgb, uniforms.color.a * coverage);
+    	}
 }
@@ -8,9 +8,15 @@ layout(location = 0) out vec2 tex_coords;
 layout(set = 0, binding = 0) uniform Data {
     	mat4 proj;
 	mat4 transform;
+	vec2 shear;
 } uniforms;
 
 void main() {
 	tex_coords = uv;
-	gl_Position = uniforms.proj * (uniforms.transform * vec4(position, 1.0));
+
+	// Leans the glyph right above the baseline in shear.y, by shear.x per unit of height.
+	vec4 world = uniforms.transform * vec4(position, 1.0);
+	world.x += uniforms.shear.x * (uniforms.shear.y - world.y);
+
+	gl_Position = uniforms.proj * world;
 }
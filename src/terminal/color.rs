@@ -0,0 +1,78 @@
+use cgmath::Vector4;
+
+pub const ANSI_COLORS: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0xcd, 0x00, 0x00],
+    [0x00, 0xcd, 0x00],
+    [0xcd, 0xcd, 0x00],
+    [0x00, 0x00, 0xee],
+    [0xcd, 0x00, 0xcd],
+    [0x00, 0xcd, 0xcd],
+    [0xe5, 0xe5, 0xe5],
+    [0x7f, 0x7f, 0x7f],
+    [0xff, 0x00, 0x00],
+    [0x00, 0xff, 0x00],
+    [0xff, 0xff, 0x00],
+    [0x5c, 0x5c, 0xff],
+    [0xff, 0x00, 0xff],
+    [0x00, 0xff, 0xff],
+    [0xff, 0xff, 0xff],
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    pub fn resolve(self, default: Vector4<f32>) -> Vector4<f32> {
+        match self {
+            Self::Default => default,
+            Self::Indexed(i) => indexed(i),
+            Self::Rgb(r, g, b) => rgb(r, g, b),
+        }
+    }
+
+    pub fn sgr(self, base: u16) -> Option<String> {
+        match self {
+            Self::Indexed(i) if i < 8 => Some(format!("{}", base + i as u16)),
+            _ => self.extended_sgr(base + 8),
+        }
+    }
+
+    pub fn extended_sgr(self, code: u16) -> Option<String> {
+        match self {
+            Self::Default => None,
+            Self::Indexed(i) => Some(format!("{};5;{}", code, i)),
+            Self::Rgb(r, g, b) => Some(format!("{};2;{};{};{}", code, r, g, b)),
+        }
+    }
+}
+
+pub fn rgb(r: u8, g: u8, b: u8) -> Vector4<f32> {
+    Vector4::new(r as f32, g as f32, b as f32, u8::MAX as f32) / u8::MAX as f32
+}
+
+pub fn indexed(i: u8) -> Vector4<f32> {
+    match i {
+        0..=15 => {
+            let [r, g, b] = ANSI_COLORS[i as usize];
+
+            rgb(r, g, b)
+        }
+        16..=231 => {
+            let i = i - 16;
+            let level = |c: u8| if c == 0 { 0 } else { 55 + c * 40 };
+
+            rgb(level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        232..=255 => {
+            let level = 8 + (i - 232) * 10;
+
+            rgb(level, level, level)
+        }
+    }
+}
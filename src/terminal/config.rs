@@ -1,55 +1,340 @@
+use crate::APP_NAME;
 use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
 use std::{
     fs::{self, File},
     io::{self, ErrorKind, Read, Write},
-    path::Path,
+    path::{Path, PathBuf},
 };
+use thiserror::Error;
+use winit::event::VirtualKeyCode;
+use winit_input_helper::WinitInputHelper;
 
 pub const DEFAULT_CONFIG_DIR: &str = "~/.config/foxterm/config.yaml";
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Font {
     pub path: String,
     pub color: [f32; 4],
     pub scale: f32,
+    // fontdue can't hint outlines, so there is no hinting option, these are the quality knobs.
+    pub antialias: bool,
+    pub gamma: f32,
+    pub stem_darkening: bool,
+    pub subpixel: bool,
+    pub subpixel_order: SubpixelOrder,
+    pub powerline_stretch: bool,
 }
 
 impl Font {
-    pub fn new(path: String, color: [f32; 4], scale: f32) -> Self {
-        Self { path, color, scale }
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        path: String,
+        color: [f32; 4],
+        scale: f32,
+        antialias: bool,
+        gamma: f32,
+        stem_darkening: bool,
+        subpixel: bool,
+        subpixel_order: SubpixelOrder,
+        powerline_stretch: bool,
+    ) -> Self {
+        Self {
+            path,
+            color,
+            scale,
+            antialias,
+            gamma,
+            stem_darkening,
+            subpixel,
+            subpixel_order,
+            powerline_stretch,
+        }
     }
 }
 
 impl Default for Font {
     fn default() -> Self {
-        Self::new("test.ttf".to_owned(), [1.0; 4], 40.0)
+        Self::new(
+            "test.ttf".to_owned(),
+            [1.0; 4],
+            40.0,
+            true,
+            1.0,
+            false,
+            false,
+            SubpixelOrder::default(),
+            true,
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubpixelOrder {
+    #[default]
+    Rgb,
+    Bgr,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FontRange {
+    pub path: String,
+    pub ranges: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct HyperlinkRule {
+    pub regex: String,
+    pub action: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CursorColor {
+    #[default]
+    Auto,
+    Fixed([f32; 4]),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CursorStyle {
+    #[default]
+    Block,
+    BlockOverEmpty,
+    Outline,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresentMode {
+    #[default]
+    Fifo,
+    Mailbox,
+    Auto,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BellMode {
+    #[default]
+    Attention,
+    Notify,
+    None,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InvalidUtf8 {
+    #[default]
+    Replace,
+    Skip,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    #[default]
+    Ansi,
+    Html,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyBinding {
+    pub key: VirtualKeyCode,
+    pub control: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl KeyBinding {
+    pub fn new(key: VirtualKeyCode, control: bool, shift: bool, alt: bool) -> Self {
+        Self {
+            key,
+            control,
+            shift,
+            alt,
+        }
     }
+
+    // The modifiers have to match exactly, so Ctrl+Shift+E does not also fire a Ctrl+E binding.
+    pub fn pressed(&self, input: &WinitInputHelper) -> bool {
+        input.key_pressed(self.key)
+            && input.held_control() == self.control
+            && input.held_shift() == self.shift
+            && input.held_alt() == self.alt
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetaModifier {
+    #[default]
+    Alt,
+    Super,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub device_index: Option<usize>,
     pub bg_color: [f32; 4],
     pub font: Font,
+    pub app_id: Option<String>,
+    pub env: Vec<(String, String)>,
+    pub unset: Vec<String>,
+    pub confirm_close: bool,
+    pub write_chunk_size: usize,
+    pub alt_sends_esc: bool,
+    pub meta_modifier: MetaModifier,
+    pub bold_is_bright: bool,
+    pub startup_command: Option<String>,
+    pub fonts: Vec<FontRange>,
+    pub cursor_animation: bool,
+    pub unfocused_fps: u32,
+    pub bottom_margin_rows: u32,
+    pub icon: Option<PathBuf>,
+    pub padding_color: Option<[f32; 4]>,
+    pub hyperlink_rules: Vec<HyperlinkRule>,
+    pub export_path: String,
+    pub debug_cells: bool,
+    pub present_mode: PresentMode,
+    pub cursor_style: CursorStyle,
+    pub parse_chunk_size: usize,
+    pub cursor_blink: bool,
+    pub dropdown: bool,
+    pub cursor_color: CursorColor,
+    pub minimum_contrast: f32,
+    pub fake_italic_skew: f32,
+    pub vulkan_validation: bool,
+    pub cursor_blink_ms: u64,
+    pub text_blink_ms: u64,
+    pub show_tabs: bool,
+    pub show_trailing_whitespace: bool,
+    pub clear_keeps_prompt: bool,
+    pub bell_mode: BellMode,
+    pub dim_after_secs: u64,
+    pub invalid_utf8: InvalidUtf8,
+    pub export_format: ExportFormat,
+    pub export_binding: KeyBinding,
+    pub reset_binding: KeyBinding,
+    pub cell_width: Option<f32>,
+    pub debug_binding: KeyBinding,
+    pub reload_binding: KeyBinding,
+    pub zoom_in_bindings: Vec<KeyBinding>,
+    pub zoom_out_bindings: Vec<KeyBinding>,
+    pub reset_font_size_binding: KeyBinding,
+    pub hide_binding: KeyBinding,
 }
 
 impl Config {
-    pub fn new(device_index: Option<usize>, bg_color: [f32; 4], font: Font) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device_index: Option<usize>,
+        bg_color: [f32; 4],
+        font: Font,
+        app_id: Option<String>,
+        env: Vec<(String, String)>,
+        unset: Vec<String>,
+        confirm_close: bool,
+        write_chunk_size: usize,
+        alt_sends_esc: bool,
+        meta_modifier: MetaModifier,
+        bold_is_bright: bool,
+        startup_command: Option<String>,
+        fonts: Vec<FontRange>,
+        cursor_animation: bool,
+        unfocused_fps: u32,
+        bottom_margin_rows: u32,
+        icon: Option<PathBuf>,
+        padding_color: Option<[f32; 4]>,
+        hyperlink_rules: Vec<HyperlinkRule>,
+        export_path: String,
+        debug_cells: bool,
+        present_mode: PresentMode,
+        cursor_style: CursorStyle,
+        parse_chunk_size: usize,
+        cursor_blink: bool,
+        dropdown: bool,
+        cursor_color: CursorColor,
+        minimum_contrast: f32,
+        fake_italic_skew: f32,
+        vulkan_validation: bool,
+        cursor_blink_ms: u64,
+        text_blink_ms: u64,
+        show_tabs: bool,
+        show_trailing_whitespace: bool,
+        clear_keeps_prompt: bool,
+        bell_mode: BellMode,
+        dim_after_secs: u64,
+        invalid_utf8: InvalidUtf8,
+        export_format: ExportFormat,
+        export_binding: KeyBinding,
+        reset_binding: KeyBinding,
+        cell_width: Option<f32>,
+        debug_binding: KeyBinding,
+        reload_binding: KeyBinding,
+        zoom_in_bindings: Vec<KeyBinding>,
+        zoom_out_bindings: Vec<KeyBinding>,
+        reset_font_size_binding: KeyBinding,
+        hide_binding: KeyBinding,
+    ) -> Self {
         Self {
             device_index,
             bg_color,
             font,
+            app_id,
+            env,
+            unset,
+            confirm_close,
+            write_chunk_size,
+            alt_sends_esc,
+            meta_modifier,
+            bold_is_bright,
+            startup_command,
+            fonts,
+            cursor_animation,
+            unfocused_fps,
+            bottom_margin_rows,
+            icon,
+            padding_color,
+            hyperlink_rules,
+            export_path,
+            debug_cells,
+            present_mode,
+            cursor_style,
+            parse_chunk_size,
+            cursor_blink,
+            dropdown,
+            cursor_color,
+            minimum_contrast,
+            fake_italic_skew,
+            vulkan_validation,
+            cursor_blink_ms,
+            text_blink_ms,
+            show_tabs,
+            show_trailing_whitespace,
+            clear_keeps_prompt,
+            bell_mode,
+            dim_after_secs,
+            invalid_utf8,
+            export_format,
+            export_binding,
+            reset_binding,
+            cell_width,
+            debug_binding,
+            reload_binding,
+            zoom_in_bindings,
+            zoom_out_bindings,
+            reset_font_size_binding,
+            hide_binding,
         }
     }
 
     pub fn from_file(path: &String) -> anyhow::Result<Self> {
         match Self::load_contents(path) {
             Ok(contents) => {
-                let config = serde_yaml::from_str(contents.as_str())?;
+                let config: Self = serde_yaml::from_str(contents.as_str())?;
+
+                config.validate()?;
 
                 Ok(config)
             }
-            Err(e) => match e.downcast_ref::<io::Error>().and_then(|e| Some(e.kind())) {
+            Err(e) => match e.downcast_ref::<io::Error>().map(|e| e.kind()) {
                 Some(ErrorKind::NotFound) => {
                     let config = Self::default();
 
@@ -57,15 +342,44 @@ impl Config {
 
                     Ok(config)
                 }
-                _ => Err(e.into()),
+                _ => Err(e),
             },
         }
     }
 
+    // Catches values serde accepts but rendering cannot use. A failed reload keeps the old config.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.font.gamma.is_nan() || self.font.gamma <= 0.0 {
+            return Err(ConfigError::Gamma(self.font.gamma));
+        }
+
+        Ok(())
+    }
+
     pub fn default_from_file() -> anyhow::Result<Self> {
         Self::from_file(&shellexpand::tilde(DEFAULT_CONFIG_DIR).as_ref().to_string())
     }
 
+    // The effective config as YAML, with keys the file sets but foxterm does not know listed as
+    // comments at the top so the output stays loadable.
+    pub fn dump(path: &String) -> anyhow::Result<String> {
+        let config = Self::from_file(path)?;
+        let contents: Value = serde_yaml::from_str(&Self::load_contents(path)?)?;
+        let mut dump = String::new();
+
+        for key in unknown_keys(&contents, &serde_yaml::to_value(Self::default())?, "") {
+            dump.push_str(&format!("# unknown key: {}\n", key));
+        }
+
+        dump.push_str(&serde_yaml::to_string(&config)?);
+
+        Ok(dump)
+    }
+
+    pub fn dump_default_file() -> anyhow::Result<String> {
+        Self::dump(&shellexpand::tilde(DEFAULT_CONFIG_DIR).as_ref().to_string())
+    }
+
     fn create_file(&self, path: &String) -> anyhow::Result<()> {
         let path = shellexpand::tilde(path);
         let path = Path::new(path.as_ref());
@@ -91,6 +405,106 @@ impl Config {
 
 impl Default for Config {
     fn default() -> Self {
-        Self::new(None, [0.0; 4], Default::default())
+        Self::new(
+            None,
+            [0.0, 0.0, 0.0, 1.0],
+            Default::default(),
+            Some(APP_NAME.to_owned()),
+            Vec::new(),
+            Vec::new(),
+            false,
+            4096,
+            true,
+            MetaModifier::Alt,
+            false,
+            None,
+            Vec::new(),
+            false,
+            10,
+            0,
+            None,
+            None,
+            Vec::new(),
+            "~/foxterm-screen.ansi".to_owned(),
+            false,
+            PresentMode::default(),
+            CursorStyle::default(),
+            4096,
+            false,
+            false,
+            CursorColor::default(),
+            1.0,
+            0.2,
+            false,
+            500,
+            750,
+            false,
+            false,
+            false,
+            BellMode::default(),
+            0,
+            InvalidUtf8::default(),
+            ExportFormat::default(),
+            KeyBinding::new(VirtualKeyCode::E, true, true, false),
+            KeyBinding::new(VirtualKeyCode::K, true, true, false),
+            None,
+            KeyBinding::new(VirtualKeyCode::D, true, true, false),
+            KeyBinding::new(VirtualKeyCode::R, true, true, false),
+            vec![
+                KeyBinding::new(VirtualKeyCode::Equals, true, false, false),
+                KeyBinding::new(VirtualKeyCode::Plus, true, false, false),
+                KeyBinding::new(VirtualKeyCode::NumpadAdd, true, false, false),
+            ],
+            vec![
+                KeyBinding::new(VirtualKeyCode::Minus, true, false, false),
+                KeyBinding::new(VirtualKeyCode::NumpadSubtract, true, false, false),
+            ],
+            KeyBinding::new(VirtualKeyCode::Key0, true, false, false),
+            KeyBinding::new(VirtualKeyCode::H, true, true, false),
+        )
+    }
+}
+
+fn unknown_keys(value: &Value, known: &Value, prefix: &str) -> Vec<String> {
+    match (value, known) {
+        (Value::Mapping(value), Value::Mapping(known)) => value
+            .iter()
+            .flat_map(|(key, value)| {
+                let name = format!("{}{}", prefix, key.as_str().unwrap_or("?"));
+
+                match known.get(key) {
+                    Some(known) => unknown_keys(value, known, &format!("{}.", name)),
+                    None => vec![name],
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Error: font gamma must be greater than 0, not {0}")]
+    Gamma(f32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamma_must_be_positive() {
+        let config = |gamma| Config {
+            font: Font {
+                gamma,
+                ..Font::default()
+            },
+            ..Config::default()
+        };
+
+        assert!(config(1.0).validate().is_ok());
+        assert!(config(0.0).validate().is_err());
+        assert!(config(-1.0).validate().is_err());
+        assert!(config(f32::NAN).validate().is_err());
     }
 }
@@ -1,19 +1,231 @@
+use super::{color::Color, image::Image};
 use crate::loaded_font::chr::Chr;
-use cgmath::Vector2;
+use cgmath::{Vector2, Vector4, VectorSpace};
 use std::sync::Arc;
 
 pub struct Drawable {
     pub render_item: RenderItem,
     pub pos: Vector2<f32>,
+    pub style: Style,
 }
 
 impl Drawable {
-    pub fn new(render_item: RenderItem, pos: Vector2<f32>) -> Self {
-        Self { render_item, pos }
+    pub fn new(render_item: RenderItem, pos: Vector2<f32>, style: Style) -> Self {
+        Self {
+            render_item,
+            pos,
+            style,
+        }
     }
 }
 
 pub enum RenderItem {
     Chr(Arc<Chr>),
+    Image(Arc<Image>),
     Space,
+    // A horizontal tab, and how many cells it moved across.
+    Tab(usize),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub blink: bool,
+    pub overline: bool,
+    pub underline: Underline,
+    pub underline_color: Color,
+}
+
+impl Style {
+    pub fn foreground(
+        &self,
+        default_fg: Vector4<f32>,
+        default_bg: Vector4<f32>,
+        bold_is_bright: bool,
+    ) -> Vector4<f32> {
+        let fg = match self.fg {
+            Color::Indexed(i @ 0..=7) if self.bold && bold_is_bright => Color::Indexed(i + 8),
+            fg => fg,
+        }
+        .resolve(default_fg);
+
+        if self.dim {
+            fg.lerp(self.background(default_bg), 0.5)
+        } else {
+            fg
+        }
+    }
+
+    pub fn background(&self, default_bg: Vector4<f32>) -> Vector4<f32> {
+        self.bg.resolve(default_bg)
+    }
+
+    pub fn underline_color(
+        &self,
+        default_fg: Vector4<f32>,
+        default_bg: Vector4<f32>,
+        bold_is_bright: bool,
+    ) -> Vector4<f32> {
+        self.underline_color
+            .resolve(self.foreground(default_fg, default_bg, bold_is_bright))
+    }
+
+    // There are no italic faces, so italics are synthesized by shearing the glyph.
+    pub fn skew(&self, skew: f32) -> f32 {
+        if self.italic {
+            skew
+        } else {
+            0.0
+        }
+    }
+
+    pub fn sgr(&self) -> String {
+        let mut sgr = vec!["0".to_owned()];
+
+        if self.bold {
+            sgr.push("1".to_owned());
+        }
+
+        if self.dim {
+            sgr.push("2".to_owned());
+        }
+
+        if self.italic {
+            sgr.push("3".to_owned());
+        }
+
+        if self.blink {
+            sgr.push("5".to_owned());
+        }
+
+        if self.overline {
+            sgr.push("53".to_owned());
+        }
+
+        sgr.extend(self.underline.sgr());
+        sgr.extend(self.fg.sgr(30));
+        sgr.extend(self.bg.sgr(40));
+        sgr.extend(self.underline_color.extended_sgr(58));
+
+        sgr.join(";")
+    }
+
+    pub fn css(
+        &self,
+        default_fg: Vector4<f32>,
+        default_bg: Vector4<f32>,
+        bold_is_bright: bool,
+    ) -> String {
+        let mut css = vec![
+            format!(
+                "color:{}",
+                css_color(self.foreground(default_fg, default_bg, bold_is_bright))
+            ),
+            format!("background:{}", css_color(self.background(default_bg))),
+        ];
+
+        if self.bold {
+            css.push("font-weight:bold".to_owned());
+        }
+
+        if self.italic {
+            css.push("font-style:italic".to_owned());
+        }
+
+        let decoration = [
+            (self.underline != Underline::None, "underline"),
+            (self.overline, "overline"),
+        ]
+        .into_iter()
+        .filter_map(|(set, line)| set.then_some(line))
+        .collect::<Vec<_>>();
+
+        if !decoration.is_empty() {
+            css.push(format!("text-decoration:{}", decoration.join(" ")));
+        }
+
+        css.join(";")
+    }
+}
+
+pub fn css_color(color: Vector4<f32>) -> String {
+    let [r, g, b] = [color.x, color.y, color.z].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Underline {
+    #[default]
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+impl Underline {
+    pub fn from_style(style: u16) -> Option<Self> {
+        match style {
+            0 => Some(Self::None),
+            1 => Some(Self::Single),
+            2 => Some(Self::Double),
+            3 => Some(Self::Curly),
+            4 => Some(Self::Dotted),
+            5 => Some(Self::Dashed),
+            _ => None,
+        }
+    }
+
+    pub fn sgr(self) -> Option<String> {
+        match self {
+            Self::None => None,
+            Self::Single => Some("4".to_owned()),
+            Self::Double => Some("4:2".to_owned()),
+            Self::Curly => Some("4:3".to_owned()),
+            Self::Dotted => Some("4:4".to_owned()),
+            Self::Dashed => Some("4:5".to_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Vector4<f32> = Vector4::new(0.0, 0.0, 0.0, 1.0);
+    const WHITE: Vector4<f32> = Vector4::new(1.0, 1.0, 1.0, 1.0);
+
+    #[test]
+    fn dim_blends_toward_a_light_default_background() {
+        let style = Style {
+            dim: true,
+            ..Style::default()
+        };
+
+        assert_eq!(
+            style.foreground(BLACK, WHITE, false),
+            Vector4::new(0.5, 0.5, 0.5, 1.0)
+        );
+        assert_eq!(style.background(WHITE), WHITE);
+    }
+
+    #[test]
+    fn dim_blends_toward_the_cell_background() {
+        let style = Style {
+            bg: Color::Rgb(0xff, 0, 0),
+            dim: true,
+            ..Style::default()
+        };
+
+        assert_eq!(
+            style.foreground(BLACK, WHITE, false),
+            Vector4::new(0.5, 0.0, 0.0, 1.0)
+        );
+    }
 }
@@ -0,0 +1,36 @@
+use super::config::HyperlinkRule;
+use regex::Regex;
+use std::{process::Command, thread};
+
+// Opens the first rule matching the text under the column. The match and its groups are handed
+// to the shell as positional parameters, so the action refers to them as $0, $1... and they are
+// never spliced into the command itself.
+pub fn open(rules: &[HyperlinkRule], text: &str, col: usize) -> anyhow::Result<bool> {
+    for rule in rules {
+        let regex = Regex::new(&rule.regex)?;
+
+        for captures in regex.captures_iter(text) {
+            let found = captures.get(0).unwrap();
+            let start = text[..found.start()].chars().count();
+            let end = start + found.as_str().chars().count();
+
+            if (start..end).contains(&col) {
+                let mut child = Command::new("sh")
+                    .arg("-c")
+                    .arg(&rule.action)
+                    .args(
+                        captures
+                            .iter()
+                            .map(|group| group.map_or("", |group| group.as_str())),
+                    )
+                    .spawn()?;
+
+                thread::spawn(move || child.wait());
+
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
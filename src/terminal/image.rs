@@ -0,0 +1,63 @@
+#[cfg(feature = "renderer")]
+use crate::item::{texture::Texture, Item};
+use crate::SCALE;
+use cgmath::Vector2;
+#[cfg(feature = "renderer")]
+use std::sync::{Arc, OnceLock};
+#[cfg(feature = "renderer")]
+use vulkano::{device::Queue, format::Format, image::ImageDimensions};
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+    #[cfg(feature = "renderer")]
+    item: OnceLock<Item>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, data: Vec<u8>) -> Self {
+        Self {
+            width,
+            height,
+            data,
+            #[cfg(feature = "renderer")]
+            item: OnceLock::new(),
+        }
+    }
+
+    pub fn dimensions(&self) -> Vector2<f32> {
+        Vector2::new(self.width as f32, self.height as f32) * SCALE
+    }
+
+    pub fn rows(&self, scale: f32) -> usize {
+        (self.dimensions().y / scale).ceil() as usize
+    }
+
+    pub fn cols(&self, cell_width: f32) -> usize {
+        (self.dimensions().x / cell_width).ceil() as usize
+    }
+
+    #[cfg(feature = "renderer")]
+    pub fn item(&self, queue: Arc<Queue>) -> anyhow::Result<&Item> {
+        if let Some(item) = self.item.get() {
+            return Ok(item);
+        }
+
+        let texture = Texture::from_data(
+            queue.device().clone(),
+            queue,
+            Format::R8G8B8A8_UNORM,
+            ImageDimensions::Dim2d {
+                width: self.width as u32,
+                height: self.height as u32,
+                array_layers: 1,
+            },
+            &self.data,
+        )?;
+
+        Ok(self
+            .item
+            .get_or_init(|| Item::new(texture, self.dimensions())))
+    }
+}
@@ -0,0 +1,121 @@
+use winit::event::VirtualKeyCode;
+
+pub const DISAMBIGUATE: u16 = 1;
+pub const STACK_LIMIT: usize = 16;
+
+pub const KEYS: [(VirtualKeyCode, u32); 40] = [
+    (VirtualKeyCode::A, 'a' as u32),
+    (VirtualKeyCode::B, 'b' as u32),
+    (VirtualKeyCode::C, 'c' as u32),
+    (VirtualKeyCode::D, 'd' as u32),
+    (VirtualKeyCode::E, 'e' as u32),
+    (VirtualKeyCode::F, 'f' as u32),
+    (VirtualKeyCode::G, 'g' as u32),
+    (VirtualKeyCode::H, 'h' as u32),
+    (VirtualKeyCode::I, 'i' as u32),
+    (VirtualKeyCode::J, 'j' as u32),
+    (VirtualKeyCode::K, 'k' as u32),
+    (VirtualKeyCode::L, 'l' as u32),
+    (VirtualKeyCode::M, 'm' as u32),
+    (VirtualKeyCode::N, 'n' as u32),
+    (VirtualKeyCode::O, 'o' as u32),
+    (VirtualKeyCode::P, 'p' as u32),
+    (VirtualKeyCode::Q, 'q' as u32),
+    (VirtualKeyCode::R, 'r' as u32),
+    (VirtualKeyCode::S, 's' as u32),
+    (VirtualKeyCode::T, 't' as u32),
+    (VirtualKeyCode::U, 'u' as u32),
+    (VirtualKeyCode::V, 'v' as u32),
+    (VirtualKeyCode::W, 'w' as u32),
+    (VirtualKeyCode::X, 'x' as u32),
+    (VirtualKeyCode::Y, 'y' as u32),
+    (VirtualKeyCode::Z, 'z' as u32),
+    (VirtualKeyCode::Key0, '0' as u32),
+    (VirtualKeyCode::Key1, '1' as u32),
+    (VirtualKeyCode::Key2, '2' as u32),
+    (VirtualKeyCode::Key3, '3' as u32),
+    (VirtualKeyCode::Key4, '4' as u32),
+    (VirtualKeyCode::Key5, '5' as u32),
+    (VirtualKeyCode::Key6, '6' as u32),
+    (VirtualKeyCode::Key7, '7' as u32),
+    (VirtualKeyCode::Key8, '8' as u32),
+    (VirtualKeyCode::Key9, '9' as u32),
+    (VirtualKeyCode::Escape, 27),
+    (VirtualKeyCode::Return, 13),
+    (VirtualKeyCode::Tab, 9),
+    (VirtualKeyCode::Back, 127),
+];
+
+pub const CURSOR_KEYS: [(VirtualKeyCode, u8); 6] = [
+    (VirtualKeyCode::Up, b'A'),
+    (VirtualKeyCode::Down, b'B'),
+    (VirtualKeyCode::Right, b'C'),
+    (VirtualKeyCode::Left, b'D'),
+    (VirtualKeyCode::Home, b'H'),
+    (VirtualKeyCode::End, b'F'),
+];
+
+// The kitty keyboard protocol, only the disambiguate flag changes what is sent.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Keyboard {
+    pub flags: u16,
+    pub stack: Vec<u16>,
+}
+
+impl Keyboard {
+    pub fn push(&mut self, flags: u16) {
+        if self.stack.len() == STACK_LIMIT {
+            self.stack.remove(0);
+        }
+
+        self.stack.push(self.flags);
+        self.flags = flags;
+    }
+
+    pub fn pop(&mut self, count: u16) {
+        for _ in 0..count.max(1) {
+            self.flags = self.stack.pop().unwrap_or(0);
+        }
+    }
+
+    pub fn set(&mut self, flags: u16, mode: u16) {
+        match mode {
+            2 => self.flags |= flags,
+            3 => self.flags &= !flags,
+            _ => self.flags = flags,
+        }
+    }
+
+    pub fn query(&self) -> String {
+        format!("\x1b[?{}u", self.flags)
+    }
+
+    pub fn encode(
+        &self,
+        code: u32,
+        shift: bool,
+        alt: bool,
+        control: bool,
+        meta: bool,
+    ) -> Option<String> {
+        if self.flags & DISAMBIGUATE == 0 {
+            return None;
+        }
+
+        let modifiers = modifiers(shift, alt, control, meta);
+
+        // Plain and shifted text, as well as unmodified Enter, Tab and Backspace stay legacy.
+        match (code, modifiers) {
+            (27, 1) => Some("\x1b[27u".to_owned()),
+            (_, 1) => None,
+            (9 | 13 | 127, _) => Some(format!("\x1b[{};{}u", code, modifiers)),
+            (_, 2) => None,
+            (_, _) => Some(format!("\x1b[{};{}u", code, modifiers)),
+        }
+    }
+}
+
+// The xterm modifier parameter, shared by CSI u and the cursor keys.
+pub fn modifiers(shift: bool, alt: bool, control: bool, meta: bool) -> u8 {
+    1 + shift as u8 + (alt as u8) * 2 + (control as u8) * 4 + (meta as u8) * 8
+}
@@ -0,0 +1,204 @@
+use super::image::Image;
+use thiserror::Error;
+
+pub const MAX_PAYLOAD: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum GraphicsError {
+    #[error("EINVAL:malformed payload")]
+    Payload,
+    #[error("EINVAL:image dimensions do not match the payload")]
+    Dimensions,
+    #[error("ENOENT:no image with id {0}")]
+    NotFound(u32),
+    #[error("ENOTSUPPORTED:{0}")]
+    Unsupported(&'static str),
+}
+
+#[derive(Default)]
+pub enum Apc {
+    #[default]
+    Ground,
+    Escape,
+    String(Vec<u8>),
+    StringEscape(Vec<u8>),
+}
+
+pub enum ApcAction {
+    Pass,
+    Skip,
+    Dispatch(Vec<u8>),
+}
+
+impl Apc {
+    // vte ignores APC strings, so they are collected alongside the parser. The payload itself
+    // is kept away from the parser, the introducer and terminator still go through it.
+    pub fn advance(&mut self, byte: u8) -> ApcAction {
+        *self = match (std::mem::take(self), byte) {
+            (Self::String(string) | Self::StringEscape(string), 0x1b) => {
+                *self = Self::StringEscape(string);
+
+                return ApcAction::Pass;
+            }
+            (Self::StringEscape(string), b'\\') => return ApcAction::Dispatch(string),
+            (Self::String(mut string), _) => {
+                if string.len() < MAX_PAYLOAD {
+                    string.push(byte);
+                }
+
+                *self = Self::String(string);
+
+                return ApcAction::Skip;
+            }
+            (Self::Escape | Self::StringEscape(_), b'_') => Self::String(Vec::new()),
+            (_, 0x1b) => Self::Escape,
+            _ => Self::Ground,
+        };
+
+        ApcAction::Pass
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GraphicsCommand {
+    pub action: u8,
+    pub format: u32,
+    pub medium: u8,
+    pub compression: Option<u8>,
+    pub width: usize,
+    pub height: usize,
+    pub id: u32,
+    pub more: bool,
+    pub quiet: u32,
+    pub delete: u8,
+    pub keep_cursor: bool,
+}
+
+impl GraphicsCommand {
+    pub fn parse(control: &[u8]) -> Self {
+        let mut command = Self::default();
+
+        for (key, value) in control
+            .split(|&b| b == b',')
+            .filter_map(|pair| pair.split_first())
+            .filter_map(|(key, rest)| Some((*key, rest.strip_prefix(b"=")?)))
+        {
+            let number = || {
+                std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|value| value.parse::<u32>().ok())
+                    .unwrap_or(0)
+            };
+            let character = value.first().copied().unwrap_or(0);
+
+            match key {
+                b'a' => command.action = character,
+                b'f' => command.format = number(),
+                b't' => command.medium = character,
+                b'o' => command.compression = Some(character),
+                b's' => command.width = number() as usize,
+                b'v' => command.height = number() as usize,
+                b'i' => command.id = number(),
+                b'm' => command.more = number() == 1,
+                b'q' => command.quiet = number(),
+                b'd' => command.delete = character,
+                b'C' => command.keep_cursor = number() == 1,
+                _ => {}
+            }
+        }
+
+        command
+    }
+
+    pub fn image(&self, payload: &[u8]) -> Result<Image, GraphicsError> {
+        if self.medium != b'd' {
+            return Err(GraphicsError::Unsupported(
+                "only direct transmission is supported",
+            ));
+        }
+
+        if self.compression.is_some() {
+            return Err(GraphicsError::Unsupported("compressed payloads"));
+        }
+
+        let data = decode_base64(payload).ok_or(GraphicsError::Payload)?;
+        let pixels = self.width.saturating_mul(self.height);
+        let data = match self.format {
+            24 if data.len() == pixels.saturating_mul(3) => data
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], u8::MAX])
+                .collect(),
+            32 if data.len() == pixels.saturating_mul(4) => data,
+            24 | 32 => return Err(GraphicsError::Dimensions),
+            _ => {
+                return Err(GraphicsError::Unsupported(
+                    "only RGB and RGBA data is supported",
+                ))
+            }
+        };
+
+        if pixels == 0 {
+            return Err(GraphicsError::Dimensions);
+        }
+
+        Ok(Image::new(self.width, self.height, data))
+    }
+
+    pub fn reply(&self, result: Result<(), GraphicsError>) -> Option<String> {
+        let message = match result {
+            Ok(()) if self.quiet == 0 => "OK".to_string(),
+            Err(e) if self.quiet < 2 => e.to_string(),
+            _ => return None,
+        };
+
+        (self.id != 0).then(|| format!("\x1b_Gi={};{}\x1b\\", self.id, message))
+    }
+}
+
+impl Default for GraphicsCommand {
+    fn default() -> Self {
+        Self {
+            action: b't',
+            format: 32,
+            medium: b'd',
+            compression: None,
+            width: 0,
+            height: 0,
+            id: 0,
+            more: false,
+            quiet: 0,
+            delete: b'a',
+            keep_cursor: false,
+        }
+    }
+}
+
+fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(input.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for &byte in input
+        .iter()
+        .filter(|b| !b.is_ascii_whitespace() && **b != b'=')
+    {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+
+        buffer = ((buffer << 6) | value as u32) & 0xffff;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            data.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(data)
+}
@@ -1,69 +1,190 @@
+pub mod color;
 pub mod config;
 pub mod drawable;
+pub mod hyperlink;
+pub mod image;
+pub mod keyboard;
+pub mod kitty;
+pub mod modes;
+pub mod mouse;
+pub mod notify;
 pub mod pty;
+pub mod sixel;
+pub mod tmux;
 
-use crate::loaded_font::{chr::Chr, LoadedFont};
-use cgmath::{Array, Vector2, Vector4, Zero};
-use config::Config;
+use crate::{
+    loaded_font::{self, chr::Chr, LoadedFont},
+    APP_NAME, SCALE,
+};
+use cgmath::{Array, Vector2, Vector4};
+use color::Color;
+use config::{Config, InvalidUtf8, MetaModifier};
 use crossbeam::channel::{self, Receiver, Sender};
-use drawable::{Drawable, RenderItem};
+use drawable::{Drawable, RenderItem, Style, Underline};
+use image::Image;
+use keyboard::{Keyboard, CURSOR_KEYS, KEYS};
+use kitty::{Apc, ApcAction, GraphicsCommand, GraphicsError, MAX_PAYLOAD};
+use modes::Modes;
+use nix::pty::Winsize;
 use pty::Pty;
+use sixel::SixelDecoder;
 use std::{
-    env,
-    sync::{Arc, RwLock},
-    thread,
+    collections::HashMap,
+    env, fs, mem,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
+use tmux::{Passthrough, PassthroughAction};
 use vte::{Params, Parser, Perform};
-use winit::event::VirtualKeyCode;
+use winit::{dpi::PhysicalSize, event::VirtualKeyCode};
 use winit_input_helper::{TextChar, WinitInputHelper};
 
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+pub const TITLE_STACK_LIMIT: usize = 10;
+pub const ZERO_WIDTH_JOINER: char = '\u{200d}';
+pub const TAB_WIDTH: usize = 8;
+pub const REPLAY_INTERVAL: Duration = Duration::from_millis(100);
+
 pub struct Terminal {
     pub config: Config,
     pub pty: Arc<Pty>,
     pub screen: Arc<RwLock<Vec<Drawable>>>,
+    pub replay: Option<(PathBuf, usize)>,
+    shutdown: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
 }
 
 impl Terminal {
-    pub fn new(config: Config, pty: Arc<Pty>, screen: Arc<RwLock<Vec<Drawable>>>) -> Self {
+    pub fn new(
+        config: Config,
+        pty: Arc<Pty>,
+        screen: Arc<RwLock<Vec<Drawable>>>,
+        replay: Option<(PathBuf, usize)>,
+    ) -> Self {
         Self {
             config,
             pty,
             screen,
+            replay,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            threads: Vec::new(),
         }
     }
 
-    pub fn init() -> anyhow::Result<Option<Self>> {
-        match Pty::spawn(env::var("SHELL").unwrap())? {
-            Some(pty) => {
-                let config = Config::default_from_file()?;
+    // An empty command runs the user's shell.
+    pub fn init(
+        command: &[String],
+        working_directory: Option<&Path>,
+        replay: Option<(PathBuf, usize)>,
+    ) -> anyhow::Result<Self> {
+        let config = Config::default_from_file()?;
+        let (rows, cols) = grid_size(
+            config.font.scale * SCALE,
+            loaded_font::cell_width(&config, None),
+            config.bottom_margin_rows as usize,
+        );
+        let winsize = winsize(rows, cols);
 
-                Ok(Some(Self::new(
-                    config,
-                    pty,
-                    Arc::new(RwLock::new(Vec::new())),
-                )))
-            }
-            None => Ok(None),
-        }
+        let shell = env::var("SHELL").unwrap();
+        let (program, program_args) = match command.split_first() {
+            Some((program, program_args)) => (program.as_str(), program_args),
+            None => (shell.as_str(), &[][..]),
+        };
+        let pty = Pty::spawn_with(
+            program,
+            program_args,
+            &config.env,
+            &config.unset,
+            working_directory,
+            &winsize,
+        )?;
+
+        Ok(Self::new(
+            config,
+            pty,
+            Arc::new(RwLock::new(Vec::new())),
+            replay,
+        ))
     }
 
     pub fn update_pty(
         &self,
         sender: &Sender<Vec<u8>>,
         input: &WinitInputHelper,
+        performer: &Performer,
     ) -> anyhow::Result<()> {
+        let held_super =
+            input.key_held(VirtualKeyCode::LWin) || input.key_held(VirtualKeyCode::RWin);
+        let encoded = KEYS
+            .iter()
+            .filter(|(key, _)| input.key_pressed_os(*key))
+            .filter_map(|(_, code)| {
+                performer.keyboard.encode(
+                    *code,
+                    input.held_shift(),
+                    input.held_alt(),
+                    input.held_control(),
+                    held_super,
+                )
+            })
+            .collect::<String>();
+
+        // Autorepeat is encoded too, and none of the step's legacy text is sent alongside, so an
+        // encoded key never also arrives as its control character.
+        if !encoded.is_empty() {
+            sender.send(encoded.into_bytes())?;
+
+            return Ok(());
+        }
+
+        let modifiers = keyboard::modifiers(
+            input.held_shift(),
+            input.held_alt(),
+            input.held_control(),
+            held_super,
+        );
+        let cursor_keys = CURSOR_KEYS
+            .iter()
+            .filter(|(key, _)| input.key_pressed_os(*key))
+            .flat_map(|(_, final_byte)| performer.modes.cursor_key(*final_byte, modifiers))
+            .collect::<Vec<_>>();
+
+        if !cursor_keys.is_empty() {
+            sender.send(cursor_keys)?;
+
+            return Ok(());
+        }
+
+        let meta = self.config.alt_sends_esc
+            && match self.config.meta_modifier {
+                MetaModifier::Alt => input.held_alt(),
+                MetaModifier::Super => held_super,
+            };
         let mut text = input
             .text()
             .into_iter()
-            .map(|c| match c {
-                TextChar::Char(c) => c as u8,
-                #[allow(clippy::char_lit_as_u8)]
-                TextChar::Back => '\u{8}' as u8,
+            .flat_map(|c| {
+                let u = match c {
+                    TextChar::Char(c) => c as u8,
+                    #[allow(clippy::char_lit_as_u8)]
+                    TextChar::Back => '\u{8}' as u8,
+                };
+
+                if meta {
+                    vec![0x1b, u]
+                } else {
+                    vec![u]
+                }
             })
             .collect::<Vec<_>>();
 
         if input.key_pressed(VirtualKeyCode::Return) {
-            text.push(b'\r');
+            text.extend(performer.modes.enter());
         } else if input.key_pressed(VirtualKeyCode::Tab) {
             text.push(b'\t');
         } else if input.key_pressed(VirtualKeyCode::LControl)
@@ -72,220 +193,1647 @@ impl Terminal {
             text.push(b'^');
         }
 
-        sender.send(text)?;
+        if !text.is_empty() {
+            sender.send(text)?;
+        }
 
         Ok(())
     }
 
-    pub fn spawn_reader(&self, font: Arc<LoadedFont>) -> Arc<RwLock<Performer>> {
+    pub fn spawn_reader(
+        &mut self,
+        font: Arc<LoadedFont>,
+        writer: Sender<Vec<u8>>,
+        window_size: PhysicalSize<u32>,
+    ) -> Arc<RwLock<Performer>> {
         let pty = self.pty.clone();
         let screen = self.screen.clone();
-        let performer = Arc::new(RwLock::new(Performer::default(font, screen)));
+        let shutdown = self.shutdown.clone();
+        let mut startup_command = self.config.startup_command.clone();
+        let parse_chunk_size = self.config.parse_chunk_size.max(1);
+        let mut performer = Performer::default(font, screen, writer, window_size);
+
+        performer.set_bottom_margin(self.config.bottom_margin_rows as usize);
+
+        // The pty was spawned before the font was loaded, so it only had an estimate of the grid.
+        if let Err(e) = self.pty.resize(&performer.winsize()) {
+            println!("Error on resize: {:?}", e);
+        }
+
+        performer.clear_keeps_prompt = self.config.clear_keeps_prompt;
+        performer.invalid_utf8 = self.config.invalid_utf8;
+
+        let performer = Arc::new(RwLock::new(performer));
 
         {
             let performer = performer.clone();
 
-            thread::spawn(move || loop {
-                match pty.read() {
-                    Ok(buf) => {
-                        let mut parser = Parser::new();
-                        let mut performer = performer.write().unwrap();
+            self.threads.push(thread::spawn(move || loop {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                match pty
+                    .poll(POLL_INTERVAL)
+                    .and_then(|ready| ready.then(|| pty.read()).transpose())
+                {
+                    Ok(None) => {}
+                    Ok(Some(buf)) => {
+                        // The lock is given up between chunks so a flood of output cannot starve
+                        // rendering and input.
+                        for chunk in buf.chunks(parse_chunk_size) {
+                            performer.write().unwrap().process(chunk);
+                            thread::yield_now();
+                        }
+
+                        let performer = performer.read().unwrap();
 
-                        for u in buf {
-                            performer.advance_parser(&mut parser, u);
+                        // Waiting for the shell's first output keeps the command from racing its prompt.
+                        if let Some(command) = startup_command.take() {
+                            performer.reply(format!("{}\r", command));
                         }
                     }
                     Err(e) => match e.downcast_ref::<nix::errno::Errno>() {
-                        Some(nix::errno::Errno::EBADF) => break,
+                        Some(nix::errno::Errno::EBADF | nix::errno::Errno::EIO) => break,
                         _ => {
                             println!("Error on read: {:?}", e);
                         }
                     },
                 }
-            });
+            }));
+        }
+
+        if let Some((path, rate)) = self.replay.clone() {
+            self.spawn_replay(performer.clone(), path, rate, parse_chunk_size);
         }
 
         performer
     }
 
-    pub fn spawn_writer(&self) -> Sender<Vec<u8>> {
+    // The replayed session is drawn alongside the shell, as if the pty had sent it, but through its
+    // own parser.
+    fn spawn_replay(
+        &mut self,
+        performer: Arc<RwLock<Performer>>,
+        path: PathBuf,
+        rate: usize,
+        parse_chunk_size: usize,
+    ) {
+        let shutdown = self.shutdown.clone();
+
+        self.threads.push(thread::spawn(move || {
+            let bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("Error reading replay {:?}: {:?}", path, e);
+
+                    return;
+                }
+            };
+            let chunk_size = match rate {
+                0 => parse_chunk_size,
+                rate => (rate * REPLAY_INTERVAL.as_millis() as usize / 1000).max(1),
+            };
+            let mut parser = Parser::new();
+
+            for chunk in bytes.chunks(chunk_size) {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                performer.write().unwrap().process_with(&mut parser, chunk);
+
+                if rate == 0 {
+                    thread::yield_now();
+                } else {
+                    thread::sleep(REPLAY_INTERVAL);
+                }
+            }
+        }));
+    }
+
+    pub fn spawn_writer(&mut self) -> Sender<Vec<u8>> {
         let (sender, receiver): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = channel::unbounded();
         let pty = self.pty.clone();
- 
-        thread::spawn(move || {
-            while let Ok(content) = receiver.recv() {
-                if let Err(e) = pty.write(&content) {
-                    match e.downcast_ref::<nix::errno::Errno>() {
-                        Some(nix::errno::Errno::EBADF) => break,
-                        _ => {
-                            println!("Error on write: {:?}", e);
+        let chunk_size = self.config.write_chunk_size.max(1);
+        let shutdown = self.shutdown.clone();
+
+        self.threads.push(thread::spawn(move || {
+            let write = |content: Vec<u8>| {
+                for chunk in content.chunks(chunk_size) {
+                    if let Err(e) = pty.write(chunk) {
+                        match e.downcast_ref::<nix::errno::Errno>() {
+                            Some(nix::errno::Errno::EBADF) => return false,
+                            _ => {
+                                println!("Error on write: {:?}", e);
+                            }
+                        }
+                    }
+                }
+
+                true
+            };
+
+            while !shutdown.load(Ordering::SeqCst) {
+                match receiver.recv_timeout(POLL_INTERVAL) {
+                    Ok(content) => {
+                        if !write(content) {
+                            return;
                         }
                     }
+                    Err(channel::RecvTimeoutError::Timeout) => {}
+                    Err(channel::RecvTimeoutError::Disconnected) => return,
                 }
             }
-        });
+
+            // Whatever was queued before the shutdown still reaches the shell.
+            for content in receiver.try_iter() {
+                if !write(content) {
+                    return;
+                }
+            }
+        }));
 
         sender
     }
+
+    pub fn shutdown(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        for thread in self.threads.drain(..) {
+            if thread.join().is_err() {
+                println!("Error on shutdown: a terminal thread panicked");
+            }
+        }
+
+        if let Err(e) = self.pty.close() {
+            println!("Error on shutdown: {:?}", e);
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
 }
 
 pub struct Performer {
     pub font: Arc<LoadedFont>,
     pub screen: Arc<RwLock<Vec<Drawable>>>,
-    pub color: Vector4<f32>,
+    pub writer: Sender<Vec<u8>>,
+    pub window_size: PhysicalSize<u32>,
+    pub style: Style,
     pub pos: Vector2<f32>,
+    pub rows: usize,
+    pub cols: usize,
+    pub bottom_margin: usize,
+    pub horizontal_margins: Option<(usize, usize)>,
+    pub dcs: Option<Dcs>,
+    pub modes: Modes,
+    pub inactive_screen: Vec<Drawable>,
+    pub saved_cursor: Option<SavedCursor>,
+    pub pending_wrap: bool,
+    pub overwriting: bool,
+    pub joining: bool,
+    pub title: String,
+    pub title_stack: Vec<String>,
+    pub apc: Apc,
+    pub passthrough: Passthrough,
+    pub graphics: Option<(GraphicsCommand, Vec<u8>)>,
+    pub images: HashMap<u32, Arc<Image>>,
+    pub keyboard: Keyboard,
+    pub last_output: Option<Instant>,
+    pub activity: bool,
+    pub bell: bool,
+    pub clear_keeps_prompt: bool,
+    pub prompt_mark: Option<f32>,
+    pub line_feed_row: f32,
+    pub invalid_utf8: InvalidUtf8,
+    parser: Parser,
 }
 
 impl Performer {
     pub fn new(
         font: Arc<LoadedFont>,
         screen: Arc<RwLock<Vec<Drawable>>>,
-        color: Vector4<f32>,
+        writer: Sender<Vec<u8>>,
+        window_size: PhysicalSize<u32>,
+        style: Style,
         pos: Vector2<f32>,
     ) -> Self {
+        let (rows, cols) = grid_size(font.scale, font.cell_width(), 0);
+
         Self {
             font,
             screen,
-            color,
+            writer,
+            window_size,
+            style,
             pos,
+            rows,
+            cols,
+            bottom_margin: 0,
+            horizontal_margins: None,
+            dcs: None,
+            modes: Modes::default(),
+            inactive_screen: Vec::new(),
+            saved_cursor: None,
+            pending_wrap: false,
+            overwriting: false,
+            joining: false,
+            title: APP_NAME.to_owned(),
+            title_stack: Vec::new(),
+            apc: Apc::default(),
+            passthrough: Passthrough::default(),
+            graphics: None,
+            images: HashMap::new(),
+            keyboard: Keyboard::default(),
+            last_output: None,
+            activity: false,
+            bell: false,
+            clear_keeps_prompt: false,
+            prompt_mark: None,
+            line_feed_row: pos.y,
+            invalid_utf8: InvalidUtf8::default(),
+            parser: Parser::new(),
         }
     }
 
-    pub fn default(font: Arc<LoadedFont>, screen: Arc<RwLock<Vec<Drawable>>>) -> Self {
-        Self::new(font, screen, Vector4::zero(), Vector2::from_value(-1.0))
+    pub fn default(
+        font: Arc<LoadedFont>,
+        screen: Arc<RwLock<Vec<Drawable>>>,
+        writer: Sender<Vec<u8>>,
+        window_size: PhysicalSize<u32>,
+    ) -> Self {
+        Self::new(
+            font,
+            screen,
+            writer,
+            window_size,
+            Style::default(),
+            Vector2::from_value(-1.0),
+        )
     }
 
-    fn add_chr(&mut self, chr: Arc<Chr>) {
-        let mut screen = self.screen.write().unwrap();
+    pub fn set_font(&mut self, font: Arc<LoadedFont>) {
+        self.font = font;
+        (self.rows, self.cols) =
+            grid_size(self.font.scale, self.font.cell_width(), self.bottom_margin);
+    }
+
+    pub fn set_bottom_margin(&mut self, bottom_margin: usize) {
+        self.bottom_margin = bottom_margin;
+        (self.rows, self.cols) =
+            grid_size(self.font.scale, self.font.cell_width(), self.bottom_margin);
+    }
+
+    pub fn winsize(&self) -> Winsize {
+        winsize(self.rows, self.cols)
+    }
+
+    pub fn reset(&mut self) {
+        self.screen.write().unwrap().clear();
+        self.inactive_screen.clear();
+        self.style = Style::default();
+        self.pos = Vector2::from_value(-1.0);
+        self.dcs = None;
+        self.modes = Modes::default();
+        self.horizontal_margins = None;
+        self.saved_cursor = None;
+        self.pending_wrap = false;
+        self.overwriting = false;
+        self.graphics = None;
+        self.images.clear();
+        self.keyboard = Keyboard::default();
+        self.title = APP_NAME.to_owned();
+        self.title_stack.clear();
+        self.apc = Apc::default();
+        self.passthrough = Passthrough::default();
+        self.bell = false;
+        self.prompt_mark = None;
+        self.line_feed_row = -1.0;
+    }
+
+    // Backspace only moves the cursor, erasing is left to the program.
+    fn backspace(&mut self) {
+        self.pending_wrap = false;
+        self.move_left(1);
+    }
 
-        self.pos.x += chr.bearing.x;
+    // With reverse wraparound, moving left from the left margin continues at the right margin of
+    // the row above. The top row never wraps.
+    fn move_left(&mut self, n: usize) {
+        let cell_width = self.font.cell_width();
 
-        let mut pos = self.pos;
+        for _ in 0..n {
+            let (left, right) = self.margins();
 
-        pos.y -= chr.bearing.y;
+            if self.pos.x > left + cell_width / 2.0 {
+                self.pos.x -= cell_width;
+            } else if self.modes.reverse_wraparound && self.pos.y > -1.0 + self.font.scale / 2.0 {
+                self.pos = Vector2::new(right, self.pos.y - self.font.scale);
+            } else {
+                break;
+            }
 
-        screen.push(Drawable::new(RenderItem::Chr(chr.clone()), pos));
+            self.overwriting = true;
+        }
+    }
 
-        self.pos.x += chr.dimensions.x;
+    // Cells are only looked up after the cursor has been moved back over written ones, appending
+    // never has anything to replace.
+    fn clear_cell(&self, screen: &mut Vec<Drawable>) {
+        if self.overwriting {
+            screen.retain(|d| {
+                matches!(d.render_item, RenderItem::Image(_))
+                    || (d.pos.x - self.pos.x).abs() >= self.font.cell_width() / 2.0
+                    || (d.pos.y - self.pos.y).abs() >= self.font.scale / 2.0
+            });
+        }
+    }
 
-        update_pos(&mut self.pos, self.font.scale, &mut screen)
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor::new(self.pos, self.style));
     }
 
-    fn add_space(&mut self) {
-        let mut screen = self.screen.write().unwrap();
+    fn restore_cursor(&mut self) {
+        if let Some(saved_cursor) = self.saved_cursor {
+            self.pos = saved_cursor.pos;
+            self.style = saved_cursor.style;
+        }
+    }
+
+    fn set_alternate_screen(&mut self, value: bool) {
+        if self.modes.alternate_screen != value {
+            mem::swap(
+                &mut *self.screen.write().unwrap(),
+                &mut self.inactive_screen,
+            );
+
+            self.modes.alternate_screen = value;
+        }
+    }
+
+    fn set_private_mode(&mut self, mode: u16, value: bool) {
+        // The alternate screen modes differ only in what they do around the switch:
+        //   47   switches buffers and nothing else.
+        //   1047 switches buffers, clearing the alternate buffer when leaving it.
+        //   1048 saves the cursor when set and restores it when reset, without switching.
+        //   1049 combines 1048 and 1047, clearing the alternate buffer on entry instead.
+        match (mode, value) {
+            (47, _) => self.set_alternate_screen(value),
+            (1047, true) => self.set_alternate_screen(true),
+            (1047, false) => {
+                if self.modes.alternate_screen {
+                    self.screen.write().unwrap().clear();
+                }
 
-        screen.push(Drawable::new(RenderItem::Space, self.pos));
+                self.set_alternate_screen(false);
+            }
+            (1048, true) => self.save_cursor(),
+            (1048, false) => self.restore_cursor(),
+            (1049, true) => {
+                self.save_cursor();
+                self.set_alternate_screen(true);
+                self.screen.write().unwrap().clear();
+            }
+            (1049, false) => {
+                self.set_alternate_screen(false);
+                self.restore_cursor();
+            }
+            (69, false) => {
+                self.modes.left_right_margin = false;
+                self.horizontal_margins = None;
+            }
+            _ => self.modes.set_private(mode, value),
+        }
+    }
 
-        self.pos.x += self.font.scale / 2.0;
+    pub fn state(&self) -> String {
+        let row = ((self.pos.y + 1.0) / self.font.scale).round() as usize;
 
-        update_pos(&mut self.pos, self.font.scale, &mut screen)
+        format!(
+            "cursor: row {} col {} ({:?}), pending wrap: {}\n\
+             grid: {}x{}, bottom margin: {}, horizontal margins: {:?}\n\
+             sgr: {}\n\
+             modes: {:?}, keyboard flags: {}\n\
+             saved cursor: {:?}\n\
+             title: {:?} ({} stacked)",
+            row + 1,
+            self.column() + 1,
+            self.pos,
+            self.pending_wrap,
+            self.rows,
+            self.cols,
+            self.bottom_margin,
+            self.horizontal_margins,
+            self.style.sgr(),
+            self.modes,
+            self.keyboard.flags,
+            self.saved_cursor,
+            self.title,
+            self.title_stack.len(),
+        )
     }
 
-    fn advance_parser(&mut self, parser: &mut Parser, u: u8) {
-        if u == 8 {
-            let mut screen = self.screen.write().unwrap();
-            let min_pos = {
-                let mut min_pos = self.pos - Vector2::new(self.font.scale / 2.0, 0.0);
+    fn set_horizontal_margins(&mut self, params: &Params) {
+        let left = param_or(params, 0, 1) as usize - 1;
+        let right = param_or(params, 1, self.cols as u16) as usize - 1;
 
-                update_pos(&mut min_pos, self.font.scale, &mut screen);
+        if left < right && right < self.cols {
+            self.horizontal_margins = Some((left, right));
+            self.pos = Vector2::from_value(-1.0);
+            self.pending_wrap = false;
+        }
+    }
 
-                min_pos
-            };
+    pub fn row_text(&self, row: usize) -> String {
+        self.row_cells(row).into_iter().map(|(c, _)| c).collect()
+    }
 
-            for i in 0..screen.len() {
-                if screen[i].pos.y == min_pos.y && screen[i].pos.x >= min_pos.x {
-                    self.pos = screen[i].pos;
+    pub fn export_ansi(&self) -> String {
+        let mut export = String::new();
 
-                    screen.remove(i);
+        for row in 0..self.rows {
+            let mut style = Style::default();
 
-                    break;
+            for (c, cell_style) in self.export_cells(row) {
+                if cell_style != style {
+                    style = cell_style;
+                    export.push_str(&format!("\x1b[{}m", style.sgr()));
                 }
+
+                export.push(c);
             }
 
-            update_pos(&mut self.pos, self.font.scale, &mut screen);
-        } else if u == b' ' {
-            self.add_space();
+            if style != Style::default() {
+                export.push_str("\x1b[0m");
+            }
 
-            update_pos(
-                &mut self.pos,
-                self.font.scale,
-                &mut self.screen.write().unwrap(),
-            )
-        } else {
-            parser.advance(self, u);
+            export.push('\n');
         }
+
+        export
     }
-}
 
-impl Perform for Performer {
-    fn print(&mut self, c: char) {
-        if let Some(chr) = self.font.get_chr_by_id(c as u8) {
-            self.add_chr(chr);
+    // A standalone page, the default colors are resolved the way the renderer would draw them.
+    pub fn export_html(
+        &self,
+        default_fg: Vector4<f32>,
+        default_bg: Vector4<f32>,
+        bold_is_bright: bool,
+    ) -> String {
+        let mut export = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n\
+             <body style=\"{}\">\n<pre>\n",
+            escape_html(&self.title),
+            Style::default().css(default_fg, default_bg, bold_is_bright),
+        );
+
+        for row in 0..self.rows {
+            let mut style = Style::default();
+
+            for (c, cell_style) in self.export_cells(row) {
+                if cell_style != style {
+                    if style != Style::default() {
+                        export.push_str("</span>");
+                    }
+
+                    style = cell_style;
+
+                    if style != Style::default() {
+                        export.push_str(&format!(
+                            "<span style=\"{}\">",
+                            style.css(default_fg, default_bg, bold_is_bright)
+                        ));
+                    }
+                }
+
+                export.push_str(&escape_html(&c.to_string()));
+            }
+
+            if style != Style::default() {
+                export.push_str("</span>");
+            }
+
+            export.push('\n');
         }
+
+        export.push_str("</pre>\n</body>\n</html>\n");
+
+        export
     }
 
-    fn csi_dispatch(
-        &mut self,
-        params: &Params,
-        _intermediates: &[u8],
-        _ignore: bool,
-        action: char,
-    ) {
-        match action {
-            'K' => {
-                if let Some([0] | []) = params.iter().next() {
-                    self.pos.x = 1.0 + self.font.scale / 2.0;
+    // Blank cells at the end of a row are left out unless they have a background to show.
+    fn export_cells(&self, row: usize) -> Vec<(char, Style)> {
+        let mut cells = self.row_cells(row);
+
+        while let Some((
+            ' ',
+            Style {
+                bg: Color::Default, ..
+            },
+        )) = cells.last()
+        {
+            cells.pop();
+        }
+
+        cells
+    }
+
+    fn row_cells(&self, row: usize) -> Vec<(char, Style)> {
+        let mut cells = vec![(' ', Style::default()); self.cols];
+        let screen = self.screen.read().unwrap();
+
+        for drawable in screen.iter() {
+            let col = ((drawable.pos.x + 1.0) / self.font.cell_width()).round() as usize;
+            let y = ((drawable.pos.y + 1.0) / self.font.scale).round() as usize;
+
+            if y != row || col >= self.cols {
+                continue;
+            }
+
+            match &drawable.render_item {
+                RenderItem::Chr(chr) if !LoadedFont::is_combining(chr.id) => {
+                    cells[col] = (chr.id, drawable.style)
                 }
+                RenderItem::Space | RenderItem::Tab(_) => cells[col] = (' ', drawable.style),
+                _ => {}
+            }
+        }
+
+        cells
+    }
+
+    pub fn cell_at(&self, x: f64, y: f64) -> (usize, usize) {
+        let cell = |position: f64, extent: u32, size: f32, count: usize| {
+            let ndc = position / extent.max(1) as f64 * 2.0;
+
+            ((ndc / size as f64).max(0.0) as usize).min(count.saturating_sub(1))
+        };
+
+        (
+            cell(x, self.window_size.width, self.font.cell_width(), self.cols),
+            cell(y, self.window_size.height, self.font.scale, self.rows),
+        )
+    }
+
+    fn column_x(&self, col: usize) -> f32 {
+        -1.0 + self.font.cell_width() * col as f32
+    }
+
+    fn column(&self) -> usize {
+        ((self.pos.x + 1.0) / self.font.cell_width()).round() as usize
+    }
+
+    // Margins only bind the cursor while it is inside them, like DEC terminals.
+    fn margins(&self) -> (f32, f32) {
+        match self.horizontal_margins {
+            Some((left, right)) if (left..=right).contains(&self.column()) => {
+                (self.column_x(left), self.column_x(right))
             }
-            'C' => match params.iter().next() {
-                Some([0] | []) => {
-                    self.pos.x += self.font.scale / 2.0;
+            _ => (-1.0, self.column_x(self.cols.saturating_sub(1))),
+        }
+    }
+
+    fn request_status(&self, request: &[u8]) {
+        let status = match request {
+            b"m" => Some(format!("{}m", self.style.sgr())),
+            b"r" => Some(format!("1;{}r", self.rows)),
+            _ => None,
+        };
+
+        match status {
+            Some(status) => self.reply(format!("\x1bP1$r{}\x1b\\", status)),
+            None => self.reply("\x1bP0$r\x1b\\".to_owned()),
+        }
+    }
+
+    fn set_graphics_rendition(&mut self, params: &Params) {
+        if params.is_empty() {
+            self.style = Style::default();
+        }
+
+        let mut params = params.iter();
+
+        while let Some(param) = params.next() {
+            match param {
+                [] | [0] => self.style = Style::default(),
+                [1] => self.style.bold = true,
+                [2] => self.style.dim = true,
+                [3] => self.style.italic = true,
+                [4] => self.style.underline = Underline::Single,
+                [5 | 6] => self.style.blink = true,
+                [4, style, ..] => {
+                    if let Some(underline) = Underline::from_style(*style) {
+                        self.style.underline = underline;
+                    }
+                }
+                [21] => self.style.underline = Underline::Double,
+                [22] => {
+                    self.style.bold = false;
+                    self.style.dim = false;
+                }
+                [23] => self.style.italic = false,
+                [24] => self.style.underline = Underline::None,
+                [25] => self.style.blink = false,
+                [n @ 30..=37] => self.style.fg = Color::Indexed((n - 30) as u8),
+                [38, subparams @ ..] => {
+                    if let Some(color) = extended_color(subparams, &mut params) {
+                        self.style.fg = color;
+                    }
+                }
+                [39] => self.style.fg = Color::Default,
+                [n @ 40..=47] => self.style.bg = Color::Indexed((n - 40) as u8),
+                [48, subparams @ ..] => {
+                    if let Some(color) = extended_color(subparams, &mut params) {
+                        self.style.bg = color;
+                    }
                 }
-                Some([n]) => {
-                    self.pos.x += self.font.scale / 2.0 * *n as f32;
+                [49] => self.style.bg = Color::Default,
+                [53] => self.style.overline = true,
+                [55] => self.style.overline = false,
+                [58, subparams @ ..] => {
+                    if let Some(color) = extended_color(subparams, &mut params) {
+                        self.style.underline_color = color;
+                    }
                 }
+                [59] => self.style.underline_color = Color::Default,
+                [n @ 90..=97] => self.style.fg = Color::Indexed((n - 90 + 8) as u8),
+                [n @ 100..=107] => self.style.bg = Color::Indexed((n - 100 + 8) as u8),
                 _ => {}
-            },
-            _ => {}
+            }
+        }
+    }
+
+    fn reply(&self, reply: String) {
+        if let Err(e) = self.writer.send(reply.into_bytes()) {
+            println!("Error on reply: {:?}", e);
         }
+    }
+
+    fn add_chr(&mut self, chr: Arc<Chr>) {
+        let screen = self.screen.clone();
+        let mut screen = screen.write().unwrap();
+
+        self.wrap_pending(&mut screen);
+        self.clear_cell(&mut screen);
+
+        screen.push(Drawable::new(
+            RenderItem::Chr(chr.clone()),
+            self.pos,
+            self.style,
+        ));
+
+        self.advance_cursor(self.font.cell_width(), &mut screen);
+    }
+
+    fn wrap_pending(&mut self, screen: &mut Vec<Drawable>) {
+        if mem::take(&mut self.pending_wrap) {
+            self.pos = Vector2::new(self.margins().0, self.pos.y + self.font.scale);
+            self.overwriting = false;
+
+            update_pos(&mut self.pos, &self.font, self.rows, self.cols, screen);
+        }
+    }
+
+    fn advance_cursor(&mut self, width: f32, screen: &mut Vec<Drawable>) {
+        // At the right margin the cursor stays on the last cell until the next printable wraps it.
+        // Positions are added up from float steps, so the edge is allowed half a cell of rounding.
+        if self.pos.x + width > self.margins().1 + self.font.cell_width() / 2.0 {
+            self.pending_wrap = true;
+        } else {
+            self.pos.x += width;
+        }
+
+        update_pos(&mut self.pos, &self.font, self.rows, self.cols, screen)
+    }
+
+    fn add_combining(&mut self, chr: Arc<Chr>) {
+        let mut screen = self.screen.write().unwrap();
+        let (pos, style) = match screen.last() {
+            Some(base) => (
+                base.pos
+                    + Vector2::new(
+                        (self.font.cell_width() - chr.dimensions.x) / 2.0 - chr.bearing.x,
+                        0.0,
+                    ),
+                base.style,
+            ),
+            None => return,
+        };
+
+        // The mark shares the base's cell, so only its glyph is drawn, never a second set of
+        // backgrounds or lines.
+        screen.push(Drawable::new(
+            RenderItem::Chr(chr),
+            pos,
+            Style {
+                bg: Color::Default,
+                overline: false,
+                underline: Underline::None,
+                ..style
+            },
+        ));
+    }
+
+    fn index(&mut self) {
+        self.pos.y += self.font.scale;
+        self.overwriting = false;
 
         update_pos(
             &mut self.pos,
-            self.font.scale,
+            &self.font,
+            self.rows,
+            self.cols,
             &mut self.screen.write().unwrap(),
-        )
-    }
-}
-
-fn update_x(pos: &mut Vector2<f32>, scale: f32) {
-    if pos.x > 1.0 - scale / 2.0 {
-        *pos = Vector2::new(
-            -2.0 + pos.x + scale / 2.0,
-            pos.y + scale * (pos.x / 2.0).ceil(),
-        );
-    } else if pos.x < -1.0 {
-        *pos = Vector2::new(
-            2.0 + pos.x - scale / 2.0,
-            pos.y - scale * (pos.x / -2.0).ceil(),
         );
     }
-}
 
-fn update_y(pos: &mut Vector2<f32>, scale: f32, screen: &mut Vec<Drawable>) {
-    if pos.y > 1.0 - scale {
-        let dif = scale * (pos.y / (1.0 - scale).ceil());
+    fn reverse_index(&mut self) {
+        if self.pos.y > -1.0 {
+            self.pos.y -= self.font.scale;
 
-        pos.y -= dif;
+            return;
+        }
 
-        screen.retain_mut(|d| {
-            d.pos.y -= dif;
+        let bottom = -1.0 + self.font.scale * (self.rows - 1) as f32;
 
-            d.pos.y > -1.0
+        self.screen.write().unwrap().retain_mut(|d| {
+            d.pos.y += self.font.scale;
+
+            d.pos.y <= bottom
         });
     }
-}
 
-fn update_pos(pos: &mut Vector2<f32>, scale: f32, screen: &mut Vec<Drawable>) {
-    update_x(pos, scale);
-    update_y(pos, scale, screen);
+    fn screen_alignment(&mut self) {
+        let mut screen = self.screen.write().unwrap();
+
+        screen.clear();
+
+        if let Some(chr) = self.font.get_chr('E') {
+            for row in 0..self.rows {
+                for col in 0..self.cols {
+                    let pos = Vector2::new(
+                        -1.0 + self.font.cell_width() * col as f32,
+                        -1.0 + self.font.scale * row as f32,
+                    );
+
+                    screen.push(Drawable::new(
+                        RenderItem::Chr(chr.clone()),
+                        pos,
+                        Style::default(),
+                    ));
+                }
+            }
+        }
+
+        self.pos = Vector2::from_value(-1.0);
+        self.pending_wrap = false;
+    }
+
+    fn add_image(&mut self, image: Arc<Image>) {
+        self.screen.write().unwrap().push(Drawable::new(
+            RenderItem::Image(image),
+            self.pos,
+            self.style,
+        ));
+    }
+
+    fn apc_dispatch(&mut self, apc: &[u8]) {
+        if let Some(graphics) = apc.strip_prefix(b"G") {
+            let (control, payload) = match graphics.iter().position(|&b| b == b';') {
+                Some(i) => (&graphics[..i], &graphics[i + 1..]),
+                None => (graphics, &[][..]),
+            };
+
+            self.graphics_command(GraphicsCommand::parse(control), payload);
+        }
+    }
+
+    fn graphics_command(&mut self, command: GraphicsCommand, payload: &[u8]) {
+        // Chunked transmissions only repeat the `m` key, everything else comes from the first chunk.
+        let (command, payload) = match self.graphics.take() {
+            Some((first, mut data)) => {
+                if data.len() < MAX_PAYLOAD {
+                    data.extend_from_slice(payload);
+                }
+
+                (
+                    GraphicsCommand {
+                        more: command.more,
+                        ..first
+                    },
+                    data,
+                )
+            }
+            None => (command, payload.to_vec()),
+        };
+
+        if command.more {
+            self.graphics = Some((command, payload));
+
+            return;
+        }
+
+        let result = match command.action {
+            b't' | b'T' | b'q' => command.image(&payload).map(|image| {
+                if command.action != b'q' {
+                    let image = Arc::new(image);
+
+                    self.images.insert(command.id, image.clone());
+
+                    if command.action == b'T' {
+                        self.place_image(image, command.keep_cursor);
+                    }
+                }
+            }),
+            b'p' => match self.images.get(&command.id) {
+                Some(image) => {
+                    self.place_image(image.clone(), command.keep_cursor);
+
+                    Ok(())
+                }
+                None => Err(GraphicsError::NotFound(command.id)),
+            },
+            // Uppercase selectors free the image data along with the placements.
+            b'd' => match command.delete {
+                b'a' | b'A' => {
+                    if command.delete == b'A' {
+                        self.images.clear();
+                    }
+
+                    self.screen
+                        .write()
+                        .unwrap()
+                        .retain(|d| !matches!(d.render_item, RenderItem::Image(_)));
+
+                    Ok(())
+                }
+                b'i' | b'I' => {
+                    let image = match command.delete {
+                        b'I' => self.images.remove(&command.id),
+                        _ => self.images.get(&command.id).cloned(),
+                    };
+
+                    if let Some(image) = image {
+                        self.screen
+                            .write()
+                            .unwrap()
+                            .retain(|d| match &d.render_item {
+                                RenderItem::Image(placed) => !Arc::ptr_eq(placed, &image),
+                                _ => true,
+                            });
+                    }
+
+                    Ok(())
+                }
+                _ => Err(GraphicsError::Unsupported(
+                    "only the a and i delete selectors are supported",
+                )),
+            },
+            _ => Err(GraphicsError::Unsupported("unknown action")),
+        };
+
+        if let Some(reply) = command.reply(result) {
+            self.reply(reply);
+        }
+    }
+
+    fn place_image(&mut self, image: Arc<Image>, keep_cursor: bool) {
+        let rows = image.rows(self.font.scale);
+        let cols = image.cols(self.font.cell_width());
+
+        self.add_image(image);
+
+        // Like kitty, the cursor ends up just past the bottom right cell of the image.
+        if !keep_cursor {
+            self.pos += Vector2::new(
+                self.font.cell_width() * cols as f32,
+                self.font.scale * rows.saturating_sub(1) as f32,
+            );
+
+            update_pos(
+                &mut self.pos,
+                &self.font,
+                self.rows,
+                self.cols,
+                &mut self.screen.write().unwrap(),
+            );
+        }
+    }
+
+    fn add_space(&mut self) {
+        let screen = self.screen.clone();
+        let mut screen = screen.write().unwrap();
+
+        self.wrap_pending(&mut screen);
+        self.clear_cell(&mut screen);
+
+        screen.push(Drawable::new(RenderItem::Space, self.pos, self.style));
+
+        self.advance_cursor(self.font.cell_width(), &mut screen);
+    }
+
+    // With clear_keeps_prompt the prompt's line survives and moves to the top, the rest of the
+    // screen is wiped either way. `clear` homes the cursor before erasing, so the prompt is found
+    // from the last OSC 133;A mark, or the row the command line was ended on.
+    fn clear_display(&mut self) {
+        let mut screen = self.screen.write().unwrap();
+
+        if self.clear_keeps_prompt {
+            let (y, scale) = (
+                self.prompt_mark.unwrap_or(self.line_feed_row),
+                self.font.scale,
+            );
+
+            screen.retain(|d| (d.pos.y - y).abs() < scale / 2.0);
+
+            for drawable in screen.iter_mut() {
+                drawable.pos.y -= y + 1.0;
+            }
+
+            self.pos.y = -1.0;
+            self.prompt_mark = self.prompt_mark.map(|_| -1.0);
+            self.line_feed_row = -1.0;
+        } else {
+            screen.clear();
+        }
+    }
+
+    // The tab is kept on screen so it can be shown, the cells it skips are left as they were.
+    fn tab(&mut self) {
+        let screen = self.screen.clone();
+        let mut screen = screen.write().unwrap();
+        let column = self.column();
+        let last = ((self.margins().1 + 1.0) / self.font.cell_width()).round() as usize;
+        let stop = ((column / TAB_WIDTH + 1) * TAB_WIDTH).min(last);
+
+        if stop > column {
+            screen.push(Drawable::new(
+                RenderItem::Tab(stop - column),
+                self.pos,
+                self.style,
+            ));
+
+            self.pos.x = self.column_x(stop);
+        }
+    }
+
+    // The one way pty output enters the terminal. The parser lives as long as the performer, so
+    // sequences and characters split across reads carry over.
+    pub fn process(&mut self, bytes: &[u8]) {
+        let mut parser = mem::take(&mut self.parser);
+
+        self.process_with(&mut parser, bytes);
+        self.parser = parser;
+    }
+
+    // Other sources, like a replay, bring their own parser so their reads never continue the
+    // shell's sequences or the other way around.
+    pub fn process_with(&mut self, parser: &mut Parser, bytes: &[u8]) {
+        for u in bytes {
+            self.advance_parser(parser, *u);
+        }
+
+        self.last_output = Some(Instant::now());
+        self.activity = true;
+    }
+
+    fn advance_parser(&mut self, parser: &mut Parser, u: u8) {
+        match self.passthrough.advance(u) {
+            PassthroughAction::Skip => return,
+            PassthroughAction::Release(held) => {
+                for held in held {
+                    self.advance_unwrapped(parser, *held);
+                }
+            }
+            PassthroughAction::Dispatch(payload) => {
+                // The parser has only seen ESC P, the terminator closes that DCS before the
+                // unwrapped sequence is parsed.
+                parser.advance(self, 0x1b);
+                parser.advance(self, b'\\');
+
+                for u in payload {
+                    self.advance_parser(parser, u);
+                }
+
+                return;
+            }
+            PassthroughAction::Pass => {}
+        }
+
+        self.advance_unwrapped(parser, u);
+    }
+
+    fn advance_unwrapped(&mut self, parser: &mut Parser, u: u8) {
+        match self.apc.advance(u) {
+            ApcAction::Skip => return,
+            ApcAction::Dispatch(apc) => {
+                parser.advance(self, u);
+                self.apc_dispatch(&apc);
+
+                return;
+            }
+            ApcAction::Pass => {}
+        }
+
+        parser.advance(self, u);
+    }
+}
+
+impl Perform for Performer {
+    fn execute(&mut self, byte: u8) {
+        // NUL is fill and is dropped like every other unhandled control code.
+        match byte {
+            7 => self.bell = true,
+            8 => self.backspace(),
+            9 => self.tab(),
+            10 => self.line_feed_row = self.pos.y,
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, c: char) {
+        if c.is_control() {
+            return;
+        }
+
+        // Spaces come through the parser like everything else, so strings that contain them, like
+        // titles, are left whole.
+        if c == ' ' {
+            self.joining = false;
+            self.add_space();
+
+            update_pos(
+                &mut self.pos,
+                &self.font,
+                self.rows,
+                self.cols,
+                &mut self.screen.write().unwrap(),
+            );
+
+            return;
+        }
+
+        // fontdue cannot shape ZWJ sequences into one glyph, so each joined component is
+        // stacked onto the cell of the first one instead of advancing.
+        if c == ZERO_WIDTH_JOINER {
+            self.joining = true;
+
+            return;
+        }
+
+        let joined = mem::take(&mut self.joining);
+        // vte prints U+FFFD for each invalid UTF-8 sequence, it takes up a cell like any other
+        // character so the rest of the line stays aligned.
+        let chr = match (c, self.invalid_utf8) {
+            (char::REPLACEMENT_CHARACTER, InvalidUtf8::Skip) => return,
+            (char::REPLACEMENT_CHARACTER, InvalidUtf8::Replace) => {
+                self.font.get_chr(c).or_else(|| self.font.get_chr('?'))
+            }
+            _ => self.font.get_chr(c),
+        };
+
+        let combining = joined || LoadedFont::is_combining(c);
+
+        match chr {
+            Some(chr) if combining => self.add_combining(chr),
+            Some(chr) => self.add_chr(chr),
+            // A character the fonts lack still takes its cell, so the rest of the line lines up.
+            None if !combining => self.add_space(),
+            None => {}
+        }
+    }
+
+    fn hook(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, action: char) {
+        self.dcs = match (intermediates, action) {
+            ([b'$'], 'q') => Some(Dcs::RequestStatus(Vec::new())),
+            ([], 'q') => {
+                let transparent = matches!(params.iter().nth(1), Some([1]));
+
+                Some(Dcs::Sixel(SixelDecoder::new(transparent)))
+            }
+            _ => None,
+        };
+    }
+
+    fn put(&mut self, byte: u8) {
+        match &mut self.dcs {
+            Some(Dcs::RequestStatus(request)) => request.push(byte),
+            Some(Dcs::Sixel(decoder)) => decoder.put(byte),
+            None => {}
+        }
+    }
+
+    fn unhook(&mut self) {
+        match self.dcs.take() {
+            Some(Dcs::RequestStatus(request)) => self.request_status(&request),
+            Some(Dcs::Sixel(decoder)) => {
+                if let Some(image) = decoder.finish() {
+                    let rows = image.rows(self.font.scale);
+
+                    self.add_image(Arc::new(image));
+                    self.pos = Vector2::new(-1.0, self.pos.y + self.font.scale * rows as f32);
+
+                    update_pos(
+                        &mut self.pos,
+                        &self.font,
+                        self.rows,
+                        self.cols,
+                        &mut self.screen.write().unwrap(),
+                    );
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        match params {
+            [b"0" | b"2", title @ ..] => {
+                self.title = String::from_utf8_lossy(&title.join(&b';')).into_owned();
+            }
+            [b"133", b"A", ..] => self.prompt_mark = Some(self.pos.y),
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        match (intermediates, byte) {
+            ([], b'D') => self.index(),
+            ([], b'E') => {
+                self.pending_wrap = false;
+                self.pos.x = self.margins().0;
+                self.index();
+            }
+            ([], b'M') => self.reverse_index(),
+            ([], b'c') => self.reset(),
+            ([b'#'], b'8') => self.screen_alignment(),
+            _ => return,
+        }
+
+        self.pending_wrap = false;
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, action: char) {
+        if let 'A'..='D' = action {
+            self.pending_wrap = false;
+        }
+
+        match action {
+            'm' if intermediates.is_empty() => self.set_graphics_rendition(params),
+            'h' | 'l' if intermediates == [b'?'] => {
+                for mode in params.iter().flatten() {
+                    self.set_private_mode(*mode, action == 'h');
+                }
+            }
+            'h' | 'l' if intermediates.is_empty() => {
+                for mode in params.iter().flatten() {
+                    self.modes.set(*mode, action == 'h');
+                }
+            }
+            // 3 J only erases scrollback, which there is none of.
+            'J' => match params.iter().next() {
+                Some([2]) => self.clear_display(),
+                // Rows that scroll off the top are dropped, so there is no scrollback to erase and
+                // the screen is left alone.
+                Some([3]) => {}
+                _ => {}
+            },
+            'K' => {
+                if let Some([0] | []) = params.iter().next() {
+                    self.pos.x = 1.0 + self.font.cell_width();
+                }
+            }
+            'A' => {
+                let n = param_or(params, 0, 1) as f32;
+
+                self.pos.y = (self.pos.y - self.font.scale * n).max(-1.0);
+            }
+            'B' => {
+                let n = param_or(params, 0, 1) as f32;
+                let bottom = -1.0 + self.font.scale * (self.rows - 1) as f32;
+
+                self.pos.y = (self.pos.y + self.font.scale * n).min(bottom);
+            }
+            'C' => {
+                let n = param_or(params, 0, 1) as f32;
+
+                self.pos.x = match self.horizontal_margins {
+                    Some(_) => (self.pos.x + self.font.cell_width() * n).min(self.margins().1),
+                    None => self.pos.x + self.font.cell_width() * n,
+                };
+            }
+            'D' => self.move_left(param_or(params, 0, 1) as usize),
+            's' if intermediates.is_empty() => {
+                if self.modes.left_right_margin {
+                    self.set_horizontal_margins(params);
+                } else {
+                    self.save_cursor();
+                }
+            }
+            'u' if intermediates.is_empty() => self.restore_cursor(),
+            'u' => match intermediates {
+                [b'?'] => self.reply(self.keyboard.query()),
+                [b'>'] => self.keyboard.push(param_or(params, 0, 0)),
+                [b'<'] => self.keyboard.pop(param_or(params, 0, 1)),
+                [b'='] => self
+                    .keyboard
+                    .set(param_or(params, 0, 0), param_or(params, 1, 1)),
+                _ => {}
+            },
+            't' => match params.iter().next() {
+                Some([14]) => self.reply(format!(
+                    "\x1b[4;{};{}t",
+                    self.window_size.height, self.window_size.width
+                )),
+                Some([18]) => self.reply(format!("\x1b[8;{};{}t", self.rows, self.cols)),
+                Some([22]) => {
+                    if let Some([] | [0] | [2]) | None = params.iter().nth(1) {
+                        if self.title_stack.len() == TITLE_STACK_LIMIT {
+                            self.title_stack.remove(0);
+                        }
+
+                        self.title_stack.push(self.title.clone());
+                    }
+                }
+                Some([23]) => {
+                    if let Some([] | [0] | [2]) | None = params.iter().nth(1) {
+                        if let Some(title) = self.title_stack.pop() {
+                            self.title = title;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+
+        update_pos(
+            &mut self.pos,
+            &self.font,
+            self.rows,
+            self.cols,
+            &mut self.screen.write().unwrap(),
+        )
+    }
+}
+
+// Count parameters treat 0 the same as a missing parameter.
+fn param_or(params: &Params, idx: usize, default: u16) -> u16 {
+    match params.iter().nth(idx).and_then(|param| param.first()) {
+        Some(0) | None => default,
+        Some(n) => *n,
+    }
+}
+
+fn extended_color<'a>(
+    subparams: &[u16],
+    params: &mut impl Iterator<Item = &'a [u16]>,
+) -> Option<Color> {
+    let mut next = || params.next().and_then(|param| param.first().copied());
+
+    match subparams {
+        [] => match next()? {
+            5 => Some(Color::Indexed(next()? as u8)),
+            2 => Some(Color::Rgb(next()? as u8, next()? as u8, next()? as u8)),
+            _ => None,
+        },
+        [5, i] => Some(Color::Indexed(*i as u8)),
+        [2, r, g, b] | [2, _, r, g, b] => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SavedCursor {
+    pub pos: Vector2<f32>,
+    pub style: Style,
+}
+
+impl SavedCursor {
+    pub fn new(pos: Vector2<f32>, style: Style) -> Self {
+        Self { pos, style }
+    }
+}
+
+pub enum Dcs {
+    RequestStatus(Vec<u8>),
+    Sixel(SixelDecoder),
+}
+
+pub fn grid_size(scale: f32, cell_width: f32, bottom_margin: usize) -> (usize, usize) {
+    let rows = (2.0 / scale) as usize;
+
+    (
+        rows.saturating_sub(bottom_margin).max(1),
+        ((2.0 / cell_width) as usize).max(1),
+    )
+}
+
+pub fn winsize(rows: usize, cols: usize) -> Winsize {
+    Winsize {
+        ws_row: rows as u16,
+        ws_col: cols as u16,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+// Anything right of the last column or left of the first carries on in the rows below or above.
+fn update_x(pos: &mut Vector2<f32>, font: &LoadedFont, cols: usize) {
+    let width = font.cell_width() * cols as f32;
+    let rows = ((pos.x + 1.0 + font.cell_width() / 2.0) / width).floor();
+
+    if rows != 0.0 {
+        pos.x -= width * rows;
+        pos.y += font.scale * rows;
+    }
+}
+
+fn update_y(pos: &mut Vector2<f32>, scale: f32, rows: usize, screen: &mut Vec<Drawable>) {
+    let bottom = -1.0 + scale * (rows - 1) as f32;
+
+    if pos.y > bottom + scale / 2.0 {
+        let dif = scale * ((pos.y - bottom) / scale).round();
+
+        pos.y -= dif;
+
+        screen.retain_mut(|d| {
+            d.pos.y -= dif;
+
+            d.pos.y > -1.0
+        });
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn update_pos(
+    pos: &mut Vector2<f32>,
+    font: &LoadedFont,
+    rows: usize,
+    cols: usize,
+    screen: &mut Vec<Drawable>,
+) {
+    update_x(pos, font, cols);
+    update_y(pos, font.scale, rows, screen);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn performer() -> Performer {
+        performer_with_cell_width(0.02)
+    }
+
+    fn performer_with_cell_width(cell_width: f32) -> Performer {
+        let (writer, _) = channel::unbounded();
+
+        Performer::default(
+            Arc::new(LoadedFont::new(HashMap::new(), None, 0.04, cell_width, 1.0)),
+            Arc::new(RwLock::new(Vec::new())),
+            writer,
+            PhysicalSize::new(800, 600),
+        )
+    }
+
+    #[test]
+    fn nul_adds_nothing() {
+        let mut performer = performer();
+
+        performer.process(b"\0");
+
+        assert!(performer.screen.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn sgr_39_resets_only_the_foreground() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b[1;4;31;42m\x1b[39m");
+
+        assert_eq!(performer.style.fg, Color::Default);
+        assert_eq!(performer.style.bg, Color::Indexed(2));
+        assert!(performer.style.bold);
+        assert_eq!(performer.style.underline, Underline::Single);
+    }
+
+    #[test]
+    fn sgr_49_resets_only_the_background() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b[1;4;31;42m\x1b[49m");
+
+        assert_eq!(performer.style.fg, Color::Indexed(1));
+        assert_eq!(performer.style.bg, Color::Default);
+        assert!(performer.style.bold);
+        assert_eq!(performer.style.underline, Underline::Single);
+    }
+
+    #[test]
+    fn character_split_across_reads() {
+        let mut performer = performer();
+
+        performer.process(&[0xe2, 0x82]);
+        performer.process(&[0xac]);
+
+        assert_eq!(performer.screen.read().unwrap().len(), 1);
+        assert_eq!(performer.column(), 1);
+    }
+
+    fn keeping_prompt() -> Performer {
+        let mut performer = performer();
+
+        performer.clear_keeps_prompt = true;
+        performer.process(b"\x1b[2B");
+
+        performer
+    }
+
+    fn rows(performer: &Performer) -> Vec<f32> {
+        let screen = performer.screen.read().unwrap();
+
+        screen.iter().map(|d| d.pos.y).collect()
+    }
+
+    // Cursor up stands in for the CUP `clear` sends, which moves the cursor off the prompt.
+    #[test]
+    fn clear_keeps_the_line_a_command_was_entered_on() {
+        let mut performer = keeping_prompt();
+
+        performer.process(b"ab\n\x1b[2A\x1b[2J\x1b[3J");
+
+        assert_eq!(rows(&performer), [-1.0, -1.0]);
+    }
+
+    #[test]
+    fn clear_keeps_the_marked_prompt() {
+        let mut performer = keeping_prompt();
+
+        performer.process(b"\x1b]133;A\x07ab\x1b[B\n\x1b[3A\x1b[2J");
+
+        assert_eq!(rows(&performer), [-1.0, -1.0]);
+    }
+
+    fn images(performer: &Performer) -> usize {
+        let screen = performer.screen.read().unwrap();
+
+        screen
+            .iter()
+            .filter(|d| matches!(d.render_item, RenderItem::Image(_)))
+            .count()
+    }
+
+    #[test]
+    fn delete_by_id_keeps_other_images() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b_Ga=T,s=1,v=1,i=1,q=2;AAAAAA==\x1b\\");
+        performer.process(b"\x1b_Ga=T,s=1,v=1,i=2,q=2;AAAAAA==\x1b\\");
+        performer.process(b"\x1b_Ga=d,d=i,i=1,q=2\x1b\\");
+
+        assert_eq!(images(&performer), 1);
+        assert_eq!(performer.images.len(), 2);
+
+        performer.process(b"\x1b_Ga=d,d=I,i=2,q=2\x1b\\");
+
+        assert_eq!(images(&performer), 0);
+        assert!(performer.images.contains_key(&1));
+        assert!(!performer.images.contains_key(&2));
+    }
+
+    #[test]
+    fn replay_does_not_continue_the_shells_sequence() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b[");
+        performer.process_with(&mut Parser::new(), b"1m");
+
+        assert!(!performer.style.bold);
+    }
+
+    #[test]
+    fn html_export_keeps_colors() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b[1;41m \x1b[0m");
+
+        let export = performer.export_html(
+            Vector4::new(1.0, 1.0, 1.0, 1.0),
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+            false,
+        );
+
+        assert!(export.contains("<body style=\"color:#ffffff;background:#000000\">"));
+        assert!(export.contains(
+            "<span style=\"color:#ffffff;background:#cd0000;font-weight:bold\"> </span>\n"
+        ));
+    }
+
+    #[test]
+    fn reset_restores_the_title() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b]2;first title\x07\x1b[22t\x1b]2;second title\x07\x07\x1bc");
+
+        assert_eq!(performer.title, APP_NAME);
+        assert!(performer.title_stack.is_empty());
+        assert!(!performer.bell);
+    }
+
+    #[test]
+    fn title_keeps_its_spaces() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b]2;my title\x07");
+
+        assert_eq!(performer.title, "my title");
+        assert!(performer.screen.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn backspace_wraps_to_the_last_column() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b[?45h\x1b[2B\x08");
+
+        assert_eq!(performer.column(), performer.cols - 1);
+        assert!((performer.pos.y - (-1.0 + performer.font.scale)).abs() < 0.001);
+    }
+
+    #[test]
+    fn cursor_left_wraps_to_the_last_column() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b[?45h\x1b[2B\x1b[3D");
+
+        assert_eq!(performer.column(), performer.cols - 3);
+        assert!((performer.pos.y - (-1.0 + performer.font.scale)).abs() < 0.001);
+    }
+
+    #[test]
+    fn cursor_left_stops_without_reverse_wraparound() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b[2B\x1b[3D\x08");
+
+        assert_eq!(performer.column(), 0);
+        assert!((performer.pos.y - (-1.0 + performer.font.scale * 2.0)).abs() < 0.001);
+    }
+
+    #[test]
+    fn full_line_waits_in_the_last_column() {
+        // Adding up this width drifts past the exact position of the last column.
+        let mut performer = performer_with_cell_width(0.0066);
+        let line = vec![b'a'; performer.cols];
+
+        performer.process(&line[..line.len() - 1]);
+
+        assert_eq!(performer.column(), performer.cols - 1);
+        assert!(!performer.pending_wrap);
+
+        performer.process(b"a");
+
+        assert_eq!(performer.column(), performer.cols - 1);
+        assert!(performer.pending_wrap);
+        assert!((performer.pos.y - -1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn next_line_returns_to_the_left_margin() {
+        let mut performer = performer();
+
+        performer.process(b"\x1b[?69h\x1b[5;20s\x1b[15Caaaaa");
+
+        assert!(performer.pending_wrap);
+
+        performer.process(b"\x1bE");
+
+        assert_eq!(performer.column(), 4);
+        assert!(!performer.pending_wrap);
+        assert!((performer.pos.y - (-1.0 + performer.font.scale)).abs() < 0.001);
+    }
+
+    #[test]
+    fn erase_scrollback_keeps_the_screen() {
+        let mut performer = performer();
+
+        performer.process(b"ab");
+
+        let pos = performer.pos;
+
+        performer.process(b"\x1b[3J");
+
+        assert_eq!(performer.screen.read().unwrap().len(), 2);
+        assert_eq!(performer.pos, pos);
+    }
 }
@@ -0,0 +1,62 @@
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Modes {
+    pub application_cursor_keys: bool,
+    pub reverse_screen: bool,
+    pub reverse_wraparound: bool,
+    pub alternate_screen: bool,
+    pub left_right_margin: bool,
+    pub mouse_tracking: MouseTracking,
+    pub sgr_mouse: bool,
+    pub line_feed_new_line: bool,
+    pub cursor_blink: Option<bool>,
+}
+
+impl Modes {
+    pub fn set(&mut self, mode: u16, value: bool) {
+        if mode == 20 {
+            self.line_feed_new_line = value;
+        }
+    }
+
+    pub fn enter(&self) -> &'static [u8] {
+        if self.line_feed_new_line {
+            b"\r\n"
+        } else {
+            b"\r"
+        }
+    }
+
+    // Application mode only changes unmodified keys, modified ones are always CSI 1;m.
+    pub fn cursor_key(&self, final_byte: u8, modifiers: u8) -> Vec<u8> {
+        match (modifiers, self.application_cursor_keys) {
+            (1, true) => vec![0x1b, b'O', final_byte],
+            (1, false) => vec![0x1b, b'[', final_byte],
+            _ => format!("\x1b[1;{}{}", modifiers, final_byte as char).into_bytes(),
+        }
+    }
+
+    pub fn set_private(&mut self, mode: u16, value: bool) {
+        match mode {
+            1 => self.application_cursor_keys = value,
+            5 => self.reverse_screen = value,
+            12 => self.cursor_blink = Some(value),
+            45 => self.reverse_wraparound = value,
+            69 => self.left_right_margin = value,
+            1000 | 1002 | 1003 if !value => self.mouse_tracking = MouseTracking::None,
+            1000 => self.mouse_tracking = MouseTracking::Normal,
+            1002 => self.mouse_tracking = MouseTracking::ButtonEvent,
+            1003 => self.mouse_tracking = MouseTracking::AnyEvent,
+            1006 => self.sgr_mouse = value,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MouseTracking {
+    #[default]
+    None,
+    Normal,
+    ButtonEvent,
+    AnyEvent,
+}
@@ -0,0 +1,86 @@
+use super::modes::{Modes, MouseTracking};
+
+pub const MOTION: u8 = 32;
+pub const WHEEL_UP: u8 = 64;
+pub const WHEEL_DOWN: u8 = 65;
+pub const X10_RELEASE: u8 = 3;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Mouse {
+    pub cell: (usize, usize),
+    pub held: Option<u8>,
+}
+
+impl Mouse {
+    pub fn moved(&mut self, cell: (usize, usize), modifiers: u8, modes: &Modes) -> Option<Vec<u8>> {
+        // Motion is only reported once per cell, not for every pixel the pointer moves.
+        if cell == self.cell {
+            return None;
+        }
+
+        self.cell = cell;
+
+        match (modes.mouse_tracking, self.held) {
+            (MouseTracking::AnyEvent, None) => {
+                Some(encode(X10_RELEASE + MOTION + modifiers, cell, false, modes))
+            }
+            (MouseTracking::AnyEvent | MouseTracking::ButtonEvent, Some(button)) => {
+                Some(encode(button + MOTION + modifiers, cell, false, modes))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn button(
+        &mut self,
+        button: u8,
+        pressed: bool,
+        modifiers: u8,
+        modes: &Modes,
+    ) -> Option<Vec<u8>> {
+        self.held = if pressed { Some(button) } else { None };
+
+        match modes.mouse_tracking {
+            MouseTracking::None => None,
+            _ => Some(encode(button + modifiers, self.cell, !pressed, modes)),
+        }
+    }
+
+    pub fn wheel(&self, up: bool, modifiers: u8, modes: &Modes) -> Option<Vec<u8>> {
+        let button = if up { WHEEL_UP } else { WHEEL_DOWN };
+
+        match modes.mouse_tracking {
+            MouseTracking::None => None,
+            _ => Some(encode(button + modifiers, self.cell, false, modes)),
+        }
+    }
+}
+
+pub fn modifiers(shift: bool, meta: bool, control: bool) -> u8 {
+    (shift as u8) * 4 + (meta as u8) * 8 + (control as u8) * 16
+}
+
+fn encode(code: u8, (col, row): (usize, usize), release: bool, modes: &Modes) -> Vec<u8> {
+    if modes.sgr_mouse {
+        let action = if release { 'm' } else { 'M' };
+
+        format!("\x1b[<{};{};{}{}", code, col + 1, row + 1, action).into_bytes()
+    } else {
+        // The legacy encoding has no release per button, and offsets everything by 32.
+        let code = if release {
+            X10_RELEASE | (code & !3)
+        } else {
+            code
+        };
+        let coordinate = |n: usize| (n + 1 + 32).min(u8::MAX as usize) as u8;
+
+        vec![
+            0x1b,
+            b'[',
+            b'M',
+            code + 32,
+            coordinate(col),
+            coordinate(row),
+        ]
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::Once;
+use thiserror::Error;
+
+static REPORTED: Once = Once::new();
+
+// Notifications are best effort, a missing daemon is reported once rather than on every bell.
+pub fn send(summary: &str, body: &str) {
+    if let Err(e) = show(summary.to_owned(), body.to_owned()) {
+        report(e);
+    }
+}
+
+fn report(e: NotifyError) {
+    REPORTED.call_once(|| println!("{}", e));
+}
+
+#[cfg(feature = "notifications")]
+fn show(summary: String, body: String) -> Result<(), NotifyError> {
+    // Talking to the daemon can block, so it is kept off the event loop.
+    std::thread::Builder::new()
+        .spawn(move || {
+            if let Err(e) = notify_rust::Notification::new()
+                .summary(&summary)
+                .body(&body)
+                .show()
+            {
+                report(NotifyError::Send(e.to_string()));
+            }
+        })
+        .map_err(|e| NotifyError::Send(e.to_string()))?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "notifications"))]
+fn show(_: String, _: String) -> Result<(), NotifyError> {
+    Err(NotifyError::Disabled)
+}
+
+#[derive(Debug, Error)]
+pub enum NotifyError {
+    #[error("Error: foxterm was built without the notifications feature")]
+    #[cfg(not(feature = "notifications"))]
+    Disabled,
+    #[error("Error sending a notification: {0}")]
+    #[cfg(feature = "notifications")]
+    Send(String),
+}
@@ -1,51 +1,167 @@
 use nix::{
-    pty,
-    unistd::{self, ForkResult},
+    errno::Errno,
+    poll::{self, PollFd, PollFlags},
+    pty::{self, Winsize},
+    sys::signal::{self, SigHandler, Signal},
+    sys::wait::{self, WaitPidFlag, WaitStatus},
+    unistd::{self, ForkResult, Pid},
+};
+use std::{
+    os::unix::{
+        io::RawFd,
+        process::{CommandExt, ExitStatusExt},
+    },
+    path::Path,
+    process::{self, Command},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+    time::Duration,
 };
-use std::{os::unix::io::RawFd, process::Command, sync::Arc};
 
 pub const BUFFER_SIZE: usize = 65536;
 
+nix::ioctl_write_ptr_bad!(set_winsize, nix::libc::TIOCSWINSZ, Winsize);
+
 pub struct Pty {
     pub fd: RawFd,
+    pub child: Pid,
+    closed: AtomicBool,
+    exit_code: OnceLock<i32>,
 }
 
 impl Pty {
-    pub fn new(fd: RawFd) -> Self {
-        Self { fd }
+    pub fn new(fd: RawFd, child: Pid) -> Self {
+        Self {
+            fd,
+            child,
+            closed: AtomicBool::new(false),
+            exit_code: OnceLock::new(),
+        }
     }
 
-    pub fn spawn(shell_path: String) -> anyhow::Result<Option<Arc<Self>>> {
-        let fork_pty = unsafe { pty::forkpty(None, None)? };
+    pub fn spawn_with(
+        program: &str,
+        args: &[String],
+        env: &[(String, String)],
+        unset: &[String],
+        working_directory: Option<&Path>,
+        winsize: &Winsize,
+    ) -> anyhow::Result<Arc<Self>> {
+        let fork_pty = unsafe { pty::forkpty(winsize, None)? };
 
         match fork_pty.fork_result {
-            ForkResult::Parent { .. } => Ok(Some(Arc::new(Self::new(fork_pty.master)))),
+            ForkResult::Parent { child } => Ok(Arc::new(Self::new(fork_pty.master, child))),
             ForkResult::Child => {
-                let _ = Command::new(shell_path).status()?;
+                // pkill -USR1 foxterm, which toggles the dropdown, matches this process too. It
+                // must survive that, while the command gets the default action back.
+                unsafe { signal::signal(Signal::SIGUSR1, SigHandler::SigIgn)? };
+
+                let mut command = Command::new(program);
+
+                unsafe {
+                    command.pre_exec(|| {
+                        signal::signal(Signal::SIGUSR1, SigHandler::SigDfl)?;
+
+                        Ok(())
+                    })
+                };
+
+                command
+                    .args(args)
+                    .env("COLUMNS", winsize.ws_col.to_string())
+                    .env("LINES", winsize.ws_row.to_string());
+
+                for key in unset {
+                    command.env_remove(key);
+                }
 
-                Ok(None)
+                if let Some(working_directory) = working_directory {
+                    command.current_dir(working_directory);
+                }
+
+                // The command's status becomes this process's, which is what exit_code reports. A
+                // command killed by a signal exits the shell way, with 128 plus the signal.
+                let code = match command.envs(env.iter().cloned()).status() {
+                    Ok(status) => status
+                        .code()
+                        .or_else(|| status.signal().map(|signal| 128 + signal))
+                        .unwrap_or(1),
+                    Err(e) => {
+                        println!("Error running {}: {:?}", program, e);
+
+                        127
+                    }
+                };
+
+                process::exit(code)
             }
         }
     }
 
+    pub fn child_alive(&self) -> bool {
+        self.exit_code().is_none()
+    }
+
+    // The child can only be reaped once, so its status is kept for later calls.
+    pub fn exit_code(&self) -> Option<i32> {
+        if let Some(code) = self.exit_code.get() {
+            return Some(*code);
+        }
+
+        let code = match wait::waitpid(self.child, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::Exited(_, code)) => code,
+            Ok(WaitStatus::Signaled(_, signal, _)) => 128 + signal as i32,
+            _ => return None,
+        };
+
+        Some(*self.exit_code.get_or_init(|| code))
+    }
+
+    pub fn resize(&self, winsize: &Winsize) -> anyhow::Result<()> {
+        unsafe { set_winsize(self.fd, winsize)? };
+
+        Ok(())
+    }
+
+    pub fn poll(&self, timeout: Duration) -> anyhow::Result<bool> {
+        let mut fds = [PollFd::new(self.fd, PollFlags::POLLIN)];
+
+        match poll::poll(&mut fds, timeout.as_millis() as i32) {
+            Ok(ready) => Ok(ready > 0),
+            Err(Errno::EINTR) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     pub fn read(&self) -> anyhow::Result<Vec<u8>> {
         let mut buffer = vec![0; BUFFER_SIZE];
 
-        unistd::read(self.fd, &mut buffer)?;
+        let read = unistd::read(self.fd, &mut buffer)?;
 
-        buffer.retain(|c| *c != 0);
+        buffer.truncate(read);
 
         Ok(buffer)
     }
 
-    pub fn write(&self, buffer: &[u8]) -> anyhow::Result<()> {
-        unistd::write(self.fd, buffer)?;
+    pub fn write(&self, mut buffer: &[u8]) -> anyhow::Result<()> {
+        while !buffer.is_empty() {
+            match unistd::write(self.fd, buffer) {
+                Ok(written) => buffer = &buffer[written..],
+                Err(Errno::EAGAIN | Errno::EINTR) => thread::yield_now(),
+                Err(e) => return Err(e.into()),
+            }
+        }
 
         Ok(())
     }
 
     pub fn close(&self) -> anyhow::Result<()> {
-        unistd::close(self.fd)?;
+        if !self.closed.swap(true, Ordering::SeqCst) {
+            unistd::close(self.fd)?;
+        }
 
         Ok(())
     }
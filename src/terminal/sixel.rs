@@ -0,0 +1,198 @@
+use super::image::Image;
+
+pub const MAX_DIMENSION: usize = 4096;
+pub const PALETTE_SIZE: usize = 256;
+
+const DEFAULT_PALETTE: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [51, 51, 204],
+    [204, 36, 36],
+    [51, 204, 51],
+    [204, 51, 204],
+    [51, 204, 204],
+    [204, 204, 51],
+    [120, 120, 120],
+    [69, 69, 69],
+    [87, 87, 153],
+    [153, 69, 69],
+    [87, 153, 87],
+    [153, 87, 153],
+    [87, 153, 153],
+    [153, 153, 87],
+    [204, 204, 204],
+];
+
+pub struct SixelDecoder {
+    palette: Vec<[u8; 4]>,
+    transparent: bool,
+    rows: Vec<Vec<Option<[u8; 4]>>>,
+    width: usize,
+    color: usize,
+    repeat: usize,
+    x: usize,
+    y: usize,
+    command: Option<u8>,
+    params: Vec<u16>,
+}
+
+impl SixelDecoder {
+    pub fn new(transparent: bool) -> Self {
+        let mut palette = vec![[0, 0, 0, u8::MAX]; PALETTE_SIZE];
+
+        for (color, [r, g, b]) in palette.iter_mut().zip(DEFAULT_PALETTE) {
+            *color = [r, g, b, u8::MAX];
+        }
+
+        Self {
+            palette,
+            transparent,
+            rows: Vec::new(),
+            width: 0,
+            color: 0,
+            repeat: 1,
+            x: 0,
+            y: 0,
+            command: None,
+            params: Vec::new(),
+        }
+    }
+
+    pub fn put(&mut self, byte: u8) {
+        match byte {
+            b'0'..=b'9' if self.command.is_some() => {
+                if self.params.is_empty() {
+                    self.params.push(0);
+                }
+
+                if let Some(param) = self.params.last_mut() {
+                    *param = param
+                        .saturating_mul(10)
+                        .saturating_add((byte - b'0') as u16);
+                }
+            }
+            b';' if self.command.is_some() => self.params.push(0),
+            _ => {
+                self.finish_command();
+
+                match byte {
+                    b'"' | b'#' | b'!' => self.command = Some(byte),
+                    b'$' => self.x = 0,
+                    b'-' => {
+                        self.x = 0;
+                        self.y += 6;
+                    }
+                    b'?'..=b'~' => self.sixel(byte - b'?'),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Option<Image> {
+        self.finish_command();
+
+        let height = self.rows.len();
+
+        if self.width == 0 || height == 0 {
+            return None;
+        }
+
+        let background = if self.transparent {
+            [0; 4]
+        } else {
+            self.palette[0]
+        };
+        let mut data = Vec::with_capacity(self.width * height * 4);
+
+        for row in &self.rows {
+            for x in 0..self.width {
+                data.extend(row.get(x).copied().flatten().unwrap_or(background));
+            }
+        }
+
+        Some(Image::new(self.width, height, data))
+    }
+
+    fn finish_command(&mut self) {
+        let params = std::mem::take(&mut self.params);
+
+        match (self.command.take(), params.as_slice()) {
+            (Some(b'#'), [color, 1, h, l, s, ..]) => {
+                self.define_color(*color as usize, hls(*h, *l, *s));
+            }
+            (Some(b'#'), [color, 2, r, g, b, ..]) => {
+                self.define_color(*color as usize, [percent(*r), percent(*g), percent(*b)]);
+            }
+            (Some(b'#'), [color, ..]) => self.color = *color as usize % PALETTE_SIZE,
+            (Some(b'!'), [repeat, ..]) => self.repeat = (*repeat as usize).clamp(1, MAX_DIMENSION),
+            (Some(b'"'), [_, _, width, height, ..]) => {
+                self.ensure_size(*width as usize, *height as usize);
+            }
+            _ => {}
+        }
+    }
+
+    fn define_color(&mut self, color: usize, [r, g, b]: [u8; 3]) {
+        self.color = color % PALETTE_SIZE;
+        self.palette[self.color] = [r, g, b, u8::MAX];
+    }
+
+    fn ensure_size(&mut self, width: usize, height: usize) {
+        let width = width.min(MAX_DIMENSION);
+        let height = height.min(MAX_DIMENSION);
+
+        if self.rows.len() < height {
+            self.rows.resize(height, Vec::new());
+        }
+
+        self.width = self.width.max(width);
+    }
+
+    fn sixel(&mut self, bits: u8) {
+        let repeat = std::mem::replace(&mut self.repeat, 1);
+        let color = self.palette[self.color];
+
+        if self.x + repeat > MAX_DIMENSION || self.y + 6 > MAX_DIMENSION {
+            return;
+        }
+
+        self.ensure_size(self.x + repeat, self.y + 6);
+
+        for i in (0..6).filter(|i| bits & (1 << i) != 0) {
+            let row = &mut self.rows[self.y + i];
+
+            if row.len() < self.x + repeat {
+                row.resize(self.x + repeat, None);
+            }
+
+            row[self.x..self.x + repeat].fill(Some(color));
+        }
+
+        self.x += repeat;
+    }
+}
+
+fn percent(value: u16) -> u8 {
+    (value.min(100) as u32 * u8::MAX as u32 / 100) as u8
+}
+
+fn hls(h: u16, l: u16, s: u16) -> [u8; 3] {
+    // Sixel hues start at blue rather than red.
+    let h = ((h as f32 + 240.0) % 360.0) / 60.0;
+    let l = l.min(100) as f32 / 100.0;
+    let s = s.min(100) as f32 / 100.0;
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let m = l - c / 2.0;
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let channel = |v: f32| ((v + m) * u8::MAX as f32).round() as u8;
+
+    [channel(r), channel(g), channel(b)]
+}
@@ -0,0 +1,82 @@
+pub const PREFIX: &[u8] = b"tmux;";
+pub const MAX_PAYLOAD: usize = 1024 * 1024;
+
+#[derive(Default)]
+pub enum Passthrough {
+    #[default]
+    Ground,
+    Escape,
+    Prefix(usize),
+    Payload(Vec<u8>),
+    PayloadEscape(Vec<u8>),
+}
+
+pub enum PassthroughAction {
+    Pass,
+    Skip,
+    // The bytes held back while the prefix could still match, to be parsed before this one.
+    Release(&'static [u8]),
+    Dispatch(Vec<u8>),
+}
+
+impl Passthrough {
+    // tmux wraps sequences for the outer terminal in ESC P tmux; ... ESC \, doubling every ESC
+    // inside. vte would hook the DCS on the t, so the prefix is matched here before the parser
+    // sees it, and the payload is unwrapped to be parsed on its own.
+    pub fn advance(&mut self, byte: u8) -> PassthroughAction {
+        *self = match (std::mem::take(self), byte) {
+            (Self::Payload(payload), 0x1b) => {
+                *self = Self::PayloadEscape(payload);
+
+                return PassthroughAction::Skip;
+            }
+            (Self::PayloadEscape(payload), b'\\') => return PassthroughAction::Dispatch(payload),
+            (Self::Payload(mut payload), _) => {
+                if payload.len() < MAX_PAYLOAD {
+                    payload.push(byte);
+                }
+
+                *self = Self::Payload(payload);
+
+                return PassthroughAction::Skip;
+            }
+            (Self::PayloadEscape(mut payload), _) => {
+                // A doubled ESC is one ESC of the inner sequence.
+                if payload.len() < MAX_PAYLOAD {
+                    payload.push(0x1b);
+
+                    if byte != 0x1b {
+                        payload.push(byte);
+                    }
+                }
+
+                *self = Self::Payload(payload);
+
+                return PassthroughAction::Skip;
+            }
+            (Self::Prefix(matched), _) if PREFIX[matched] == byte => {
+                *self = if matched + 1 == PREFIX.len() {
+                    Self::Payload(Vec::new())
+                } else {
+                    Self::Prefix(matched + 1)
+                };
+
+                return PassthroughAction::Skip;
+            }
+            (Self::Prefix(matched), _) => {
+                *self = if byte == 0x1b {
+                    Self::Escape
+                } else {
+                    Self::Ground
+                };
+
+                return PassthroughAction::Release(&PREFIX[..matched]);
+            }
+            (Self::Escape, b'P') => Self::Prefix(0),
+            (_, 0x1b) => Self::Escape,
+            _ => Self::Ground,
+        };
+
+        PassthroughAction::Pass
+    }
+}